@@ -0,0 +1,204 @@
+use std::path::Path;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use sim::{dto, Sim};
+
+use crate::{parse_number, read_json, read_task, take_option};
+
+pub struct BenchOptions<'a> {
+    /// Override the time limit of every task
+    time: Option<f32>,
+    json: Option<&'a str>,
+    baseline: Option<&'a str>,
+}
+
+impl<'a> BenchOptions<'a> {
+    pub fn take(args: &mut Vec<&'a str>) -> Result<Self, String> {
+        let time = match take_option(args, "--time")? {
+            Some(time) => match parse_number("--time", time)? {
+                t if t >= 0.0 => Some(t),
+                _ => return Err(format!("invalid value for '--time': '{time}'")),
+            },
+            None => None,
+        };
+        let json = take_option(args, "--json")?;
+        let baseline = take_option(args, "--baseline")?;
+        Ok(Self {
+            time,
+            json,
+            baseline,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BenchResult {
+    pub task: String,
+    pub points: u32,
+    pub at_turn: u32,
+    /// Seconds until the solution was available
+    pub time: f32,
+    /// Whether the solver found any solution, results of earlier runs without this field had one
+    #[serde(default = "solved_by_default")]
+    pub solved: bool,
+    /// Whether the solution could be placed on the task and reproduces the score
+    pub valid: bool,
+    /// Upper bound of the points reachable within the turn limit
+    #[serde(default)]
+    pub bound: u32,
+}
+
+/// Run the solver on every `*/task.json` in `tasks_dir` and report the scores
+pub fn bench(
+    tasks_dir: &str,
+    config: solver::SolverConfig,
+    options: BenchOptions,
+) -> Result<(), String> {
+    let baseline: Option<Vec<BenchResult>> = match options.baseline {
+        Some(path) => Some(read_json(path)?),
+        None => None,
+    };
+
+    let mut task_names = std::fs::read_dir(tasks_dir)
+        .map_err(|e| format!("{tasks_dir}: {e}"))?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            entry
+                .path()
+                .join("task.json")
+                .is_file()
+                .then(|| entry.file_name())
+        })
+        .filter_map(|name| name.into_string().ok())
+        .collect::<Vec<_>>();
+    task_names.sort();
+    if task_names.is_empty() {
+        return Err(format!("{tasks_dir}: no tasks found"));
+    }
+
+    println!("task        points   bound      %  at_turn     time  valid   baseline");
+    let mut results = Vec::with_capacity(task_names.len());
+    let mut regressions = 0;
+    for name in task_names {
+        let path = Path::new(tasks_dir).join(&name).join("task.json");
+        let task = read_task(&path.to_string_lossy())?;
+        let mut sim = Sim::try_from(&task).map_err(|e| format!("{}: {e}", path.display()))?;
+        if let Some(time) = options.time {
+            sim.time = time;
+        }
+
+        let bound = solver::score_bound(&sim).reachable_points;
+
+        let cancel = solver::CancelToken::new();
+        let start = Instant::now();
+        let result = solver::solve_sim(
+            &sim,
+            config.clone(),
+            start,
+            &cancel,
+            |_| (),
+            |solution| {
+                let time = start.elapsed().as_secs_f32();
+                let Some(solution) = solution else {
+                    return BenchResult {
+                        task: name.clone(),
+                        points: 0,
+                        at_turn: 0,
+                        time,
+                        solved: false,
+                        valid: false,
+                        bound,
+                    };
+                };
+
+                let dto_solution = dto::Solution::added(&sim, &solution.sim);
+                let mut validation_sim = Sim::try_from(&task).expect("task to be valid");
+                let valid = dto_solution.place(&mut validation_sim).is_ok()
+                    && sim::run(&validation_sim) == solution.run;
+
+                BenchResult {
+                    task: name.clone(),
+                    points: solution.run.points,
+                    at_turn: solution.run.at_turn,
+                    time,
+                    solved: true,
+                    valid,
+                    bound,
+                }
+            },
+        );
+
+        let baseline = baseline.iter().flatten().find(|b| b.task == result.task);
+        let comparison = match baseline {
+            Some(b) if result.points < b.points => {
+                regressions += 1;
+                format!("{:+} !", result.points as i64 - b.points as i64)
+            }
+            Some(b) => format!("{:+}", result.points as i64 - b.points as i64),
+            None => String::new(),
+        };
+        // the bound is wrong if it's beaten
+        let exceeded = result.points > result.bound;
+        println!(
+            "{:<10} {:>7} {:>7} {:>5.1}%{}{:>8} {:>7.2}s  {:<5} {:>10}",
+            result.task,
+            result.points,
+            result.bound,
+            percentage(result.points, result.bound),
+            if exceeded { "!" } else { " " },
+            result.at_turn,
+            result.time,
+            match (result.solved, result.valid) {
+                (false, _) => "none",
+                (true, true) => "yes",
+                (true, false) => "NO",
+            },
+            comparison,
+        );
+
+        results.push(result);
+    }
+
+    let total: u32 = results.iter().map(|r| r.points).sum();
+    let total_bound: u32 = results.iter().map(|r| r.bound).sum();
+    println!(
+        "total {total:>11} {total_bound:>7} {:>5.1}%",
+        percentage(total, total_bound)
+    );
+
+    if let Some(path) = options.json {
+        let json = serde_json::to_string_pretty(&results).expect("results to be serializable");
+        std::fs::write(path, json + "\n").map_err(|e| format!("{path}: {e}"))?;
+    }
+
+    // nothing to find for tasks where no points can be made
+    let unsolved = results.iter().filter(|r| !r.solved && r.bound > 0).count();
+    if unsolved > 0 {
+        return Err(format!("{unsolved} tasks have no solution"));
+    }
+    let invalid = results.iter().filter(|r| r.solved && !r.valid).count();
+    if invalid > 0 {
+        return Err(format!("{invalid} solutions are invalid"));
+    }
+    let exceeded = results.iter().filter(|r| r.points > r.bound).count();
+    if exceeded > 0 {
+        return Err(format!("{exceeded} tasks score more than their bound"));
+    }
+    if regressions > 0 {
+        return Err(format!("{regressions} tasks score lower than the baseline"));
+    }
+
+    Ok(())
+}
+
+fn solved_by_default() -> bool {
+    true
+}
+
+fn percentage(points: u32, bound: u32) -> f32 {
+    match (points, bound) {
+        (0, 0) => 100.0,
+        _ => points as f32 / bound as f32 * 100.0,
+    }
+}
@@ -1,56 +1,413 @@
+use std::cell::RefCell;
 use std::io::Write as _;
-use std::sync::Mutex;
-use std::time::{Duration, Instant};
+use std::process::ExitCode;
+use std::time::Instant;
 
-use sim::{dto, Sim};
+use sim::{dto, Building, Sim, FACTORY_SIZE};
 
-fn main() {
-    let start = Instant::now();
+use bench::BenchOptions;
+
+mod bench;
+mod signal;
+
+const USAGE: &str = "\
+Usage:
+    profit_cli [<task>]                         read a task from <task>, or from stdin if it's
+                                                left out, and print a solution
+    profit_cli validate <task> <solution>       place a solution on top of a task and score it
+    profit_cli render <task> [<solution>]       print the board of a task and optionally a solution
+    profit_cli svg <task> [<solution>]          export a task and optionally a solution as SVG
+    profit_cli bench [<tasks>]                  run the solver on every task in <tasks>, ./tasks by
+                                                default, and report the scores
 
-    let stdin = std::io::stdin();
-    let mut input = String::new();
-    stdin.read_line(&mut input).expect("some input data");
+Solver options:
+    --config <file>             read the solver configuration from a JSON file, the options below
+                                override its values
+    --seed <n>                  seed of the solver, 0 by default
+    --iterations <n>            stop the solver after <n> connection attempts instead of at the
+                                time limit, which makes runs reproducible
+    --safety-margin <secs>      stop the solver this long before the time limit, 0.1 by default
+    --max-combinations <n>      regional solution combinations to evaluate, 1000 by default
+    --max-non-improvements <n>  stop connecting deposits to a factory after <n> connections
+                                without improvement, 5 by default
+    --max-route-nodes <n>       path buildings to expand at most when connecting a deposit to a
+                                factory, 20000 by default
+    --route-objective <o>       optimize paths for the earliest arrival of resources with
+                                'arrival', or for the fewest covered cells with 'footprint',
+                                'arrival' by default
+    --max-factories <n>         factories to build at most per region, 4 by default
+    --factory-candidates <n>    free factory positions per product to try when adding another
+                                factory to a region, 8 by default
+    --improvement-steps <n>     mutations tried when improving the best solution with an iteration
+                                budget, 200 by default
+    --initial-temperature <t>   points a worse mutation may lose to be accepted at the start of
+                                the improvement phase, 10 by default
+    --threads <n>               worker threads searching factory positions in parallel, one per
+                                CPU core by default
 
-    let task: dto::Task = serde_json::from_str(&input).expect("valid task input format");
-    let sim = Sim::try_from(&task).expect("task input to be valid");
+Output options:
+    --output <file>             write every improved solution to <file> as soon as it's found,
+                                starting with an empty solution, the file is replaced atomically
+    --stream                    print every improved solution as a line of JSON, starting with an
+                                empty solution, instead of only the best one at the end
 
-    let best_solution = Mutex::new(None);
+Options:
+    --no-color                  don't use ANSI escape codes when rendering
+    --distances <index>         overlay the SVG with the distances to the task object at <index>
 
-    std::thread::scope(|s| {
-        let (connect_handle, combine_handle) = solver::solve(&sim, s, &best_solution, start);
+Bench options:
+    --time <secs>               override the time limit of every task
+    --json <file>               write the results as JSON to <file>
+    --baseline <file>           compare the results to the JSON results of an earlier run, fail if
+                                a task scores lower";
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let mut args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let no_color = take_flag(&mut args, "--no-color");
+    let stream = take_flag(&mut args, "--stream");
+    let options = (|| {
+        let config = solver_config(&mut args)?;
+        let distances = take_option(&mut args, "--distances")?;
+        let output = take_option(&mut args, "--output")?;
+        let bench_options = BenchOptions::take(&mut args)?;
+        Ok::<_, String>((config, distances, output, bench_options))
+    })();
+    let (config, distances, output, bench_options) = match options {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
 
-        let safety_solution_submit_duration = Duration::from_secs_f32(0.1);
-        let sim_time_limit = Duration::from_secs_f32(sim.time) - safety_solution_submit_duration;
+    let res = match args.as_slice() {
+        [] => solve(None, config, output, stream),
+        ["validate", task_path, solution_path] => validate(task_path, solution_path),
+        ["render", task_path] => render(task_path, None, !no_color),
+        ["render", task_path, solution_path] => render(task_path, Some(solution_path), !no_color),
+        ["svg", task_path] => svg(task_path, None, distances),
+        ["svg", task_path, solution_path] => svg(task_path, Some(solution_path), distances),
+        ["bench"] => bench::bench("tasks", config, bench_options),
+        ["bench", tasks_dir] => bench::bench(tasks_dir, config, bench_options),
+        [task_path] => solve(Some(task_path), config, output, stream),
+        _ => Err(USAGE.to_string()),
+    };
 
-        loop {
-            let now = Instant::now();
-            let sleep_duration = sim_time_limit - (now - start);
-            if sleep_duration > Duration::from_secs(2) {
-                std::thread::sleep(Duration::from_secs(1));
-                if connect_handle.is_finished() && combine_handle.is_finished() {
-                    break;
-                }
-            } else {
-                std::thread::sleep(sleep_duration);
-                break;
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Solve the task at `task_path`, or on stdin without a path. The best solution is printed at the
+/// end, or every improvement as it's found when streaming. An empty solution is put out first, so
+/// that there is a valid solution even if the process is killed early.
+fn solve(
+    task_path: Option<&str>,
+    config: solver::SolverConfig,
+    output: Option<&str>,
+    stream: bool,
+) -> Result<(), String> {
+    let start = Instant::now();
+
+    let sim = match task_path {
+        Some(task_path) => load_sim(task_path)?,
+        None => {
+            let input =
+                std::io::read_to_string(std::io::stdin()).map_err(|e| format!("stdin: {e}"))?;
+            let task = parse_task("stdin", &input)?;
+            Sim::try_from(&task).map_err(|e| format!("stdin: {e}"))?
+        }
+    };
+
+    let cancel = solver::CancelToken::new();
+    signal::cancel_on_signals(&cancel);
+
+    // the solution put out last, improvements found after the time limit only reach the end
+    let emitted = RefCell::new(dto::Solution::new(Vec::new()));
+    let emit = |solution: dto::Solution| -> Result<(), String> {
+        let res = output.map_or(Ok(()), |path| write_solution(path, &solution));
+        if stream {
+            print_solution(&solution);
+        }
+        *emitted.borrow_mut() = solution;
+        res
+    };
+    emit(dto::Solution::new(Vec::new()))?;
+
+    let on_improvement = |solution: &solver::ScoredSolution| {
+        if let Err(e) = emit(dto::Solution::added(&sim, &solution.sim)) {
+            eprintln!("{e}");
+        }
+    };
+    solver::solve_sim(&sim, config, start, &cancel, on_improvement, |solution| {
+        let best = match solution {
+            Some(solution) => dto::Solution::added(&sim, &solution.sim),
+            None => dto::Solution::new(Vec::new()),
+        };
+        if best != *emitted.borrow() {
+            if let Err(e) = emit(best.clone()) {
+                eprintln!("{e}");
             }
         }
+        if !stream {
+            print_solution(&best);
+        }
+    });
+
+    Ok(())
+}
+
+fn print_solution(solution: &dto::Solution) {
+    let mut stdout = std::io::stdout().lock();
+    serde_json::to_writer(&mut stdout, solution).expect("at this point we're fucked");
+    stdout.write_all(b"\n").expect("stdio to be writable");
+    stdout.flush().expect("stdio to be writable");
+}
+
+/// Write the solution to a temporary file next to `path` and move it into place, so that readers
+/// never see a partially written solution
+fn write_solution(path: &str, solution: &dto::Solution) -> Result<(), String> {
+    let tmp_path = format!("{path}.tmp");
+    let json = serde_json::to_vec(solution).map_err(|e| format!("{path}: {e}"))?;
+    std::fs::write(&tmp_path, json).map_err(|e| format!("{tmp_path}: {e}"))?;
+    std::fs::rename(&tmp_path, path).map_err(|e| format!("{path}: {e}"))
+}
 
-        let lock = best_solution.lock().expect("lock not to be poisoned");
-        match lock.as_ref() {
-            Some(solution) => {
-                let dto_solution = dto::Solution::from(&solution.sim);
-                let mut stdout = std::io::stdout();
-                serde_json::to_writer(&mut stdout, &dto_solution)
-                    .expect("at this point we're fucked");
-                stdout.write_all(b"\n").expect("stdio to be writable");
+fn validate(task_path: &str, solution_path: &str) -> Result<(), String> {
+    let mut sim = load_sim(task_path)?;
+    let solution: dto::Solution = read_json(solution_path)?;
+
+    let conflicts = solution.place_all(&mut sim);
+    if !conflicts.is_empty() {
+        for c in conflicts.iter() {
+            eprintln!("{solution_path}: {c}");
+        }
+        return Err(format!(
+            "{} of {} objects could not be placed",
+            conflicts.len(),
+            solution.objects().len()
+        ));
+    }
+
+    let run = sim::run(&sim);
+    println!("{run}");
+
+    // explain the score by the resources every factory receives and the paths leading there
+    let paths = solver::measure_paths(&sim);
+    for f in sim::analyze(&sim).factories.iter() {
+        println!(
+            "factory {}, product {}: {} products, {} points",
+            f.id.0, f.product_type as u8, f.expected_products, f.expected_points
+        );
+        for d in f.deposits.iter() {
+            println!(
+                "  deposit {}, resource {}: {} resources at {} per turn from turn {}",
+                d.deposit_id.0,
+                d.resource_type as u8,
+                d.delivered,
+                sim::MINE_RATE * d.paths,
+                d.first_arrival
+            );
+        }
+        for p in paths.iter().filter(|p| p.factory_id == f.id) {
+            println!(
+                "  path from mine {}: {} buildings, {} cells",
+                p.mine_id.0, p.stats.buildings, p.stats.cells
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn render(task_path: &str, solution_path: Option<&str>, color: bool) -> Result<(), String> {
+    let mut sim = load_sim(task_path)?;
+    if let Some(solution_path) = solution_path {
+        place_solution(&mut sim, solution_path)?;
+    }
+
+    println!("{}", sim::Render::new(&sim).color(color));
+
+    Ok(())
+}
+
+fn svg(
+    task_path: &str,
+    solution_path: Option<&str>,
+    distances: Option<&str>,
+) -> Result<(), String> {
+    let mut sim = load_sim(task_path)?;
+
+    // distances are mapped on the task alone, like the solver does
+    let distance_map = match distances {
+        Some(index) => {
+            let id = index
+                .parse()
+                .ok()
+                .map(sim::Id)
+                .filter(|id| matches!(sim.buildings.values.get(id.0 as usize), Some(Some(_))))
+                .ok_or_else(|| format!("{task_path}: no object at index '{index}'"))?;
+            let (pos, width, height) = match &sim.buildings[id] {
+                Building::Deposit(d) => (d.pos, d.width as i8, d.height as i8),
+                Building::Obstacle(o) => (o.pos, o.width as i8, o.height as i8),
+                Building::Factory(f) => (f.pos, FACTORY_SIZE, FACTORY_SIZE),
+                _ => return Err(format!("{task_path}: object {index} is not rectangular")),
+            };
+            Some(solver::map_distances(&sim, pos, width, height))
+        }
+        None => None,
+    };
+
+    if let Some(solution_path) = solution_path {
+        place_solution(&mut sim, solution_path)?;
+    }
+
+    let overlay = |p| distance_map.as_ref().and_then(|m| m[p]);
+    let mut svg = sim::Svg::new(&sim);
+    if distance_map.is_some() {
+        svg = svg.overlay(&overlay);
+    }
+    print!("{svg}");
+
+    Ok(())
+}
+
+fn load_sim(task_path: &str) -> Result<Sim, String> {
+    let task = read_task(task_path)?;
+    Sim::try_from(&task).map_err(|e| format!("{task_path}: {e}"))
+}
+
+fn read_task(path: &str) -> Result<dto::Task, String> {
+    let input = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+    parse_task(path, &input)
+}
+
+/// Parse a task, naming the malformed object or product if there is one
+fn parse_task(name: &str, input: &str) -> Result<dto::Task, String> {
+    if input.trim().is_empty() {
+        return Err(format!("{name}: no task given"));
+    }
+    serde_json::from_str(input).map_err(|e| {
+        // serde_json only tells the line and column, look for the entry that can't be read
+        let Ok(task) = serde_json::from_str::<serde_json::Value>(input) else {
+            return format!("{name}: {e}");
+        };
+        match (
+            malformed_entry::<dto::TaskObject>(&task, "objects"),
+            malformed_entry::<dto::Product>(&task, "products"),
+        ) {
+            (Some(index), _) => format!("{name}: object {index}: {e}"),
+            (None, Some(index)) => format!("{name}: product {index}: {e}"),
+            (None, None) => format!("{name}: {e}"),
+        }
+    })
+}
+
+/// Index of the first entry of the array at `key` that isn't a valid `T`
+fn malformed_entry<T: serde::de::DeserializeOwned>(
+    task: &serde_json::Value,
+    key: &str,
+) -> Option<usize> {
+    let entries = task.get(key)?.as_array()?;
+    entries
+        .iter()
+        .position(|entry| T::deserialize(entry).is_err())
+}
+
+/// Place whatever can be placed of a solution, printing all conflicts
+fn place_solution(sim: &mut Sim, solution_path: &str) -> Result<(), String> {
+    let solution: dto::Solution = read_json(solution_path)?;
+    for c in solution.place_all(sim) {
+        eprintln!("{solution_path}: {c}");
+    }
+    Ok(())
+}
+
+fn solver_config(args: &mut Vec<&str>) -> Result<solver::SolverConfig, String> {
+    let mut config = match take_option(args, "--config")? {
+        Some(path) => read_json(path)?,
+        None => solver::SolverConfig::default(),
+    };
+    if let Some(seed) = take_option(args, "--seed")? {
+        config.seed = parse_number("--seed", seed)?;
+    }
+    if let Some(iterations) = take_option(args, "--iterations")? {
+        config.budget = solver::Budget::Iterations(parse_number("--iterations", iterations)?);
+    }
+    if let Some(margin) = take_option(args, "--safety-margin")? {
+        config.safety_margin = parse_number("--safety-margin", margin)?;
+    }
+    if let Some(n) = take_option(args, "--max-combinations")? {
+        config.max_combinations = parse_number("--max-combinations", n)?;
+    }
+    if let Some(n) = take_option(args, "--max-non-improvements")? {
+        config.max_non_improvements = parse_number("--max-non-improvements", n)?;
+    }
+    if let Some(n) = take_option(args, "--max-route-nodes")? {
+        config.max_route_nodes = parse_number("--max-route-nodes", n)?;
+    }
+    if let Some(objective) = take_option(args, "--route-objective")? {
+        config.route_objective = match objective {
+            "arrival" => solver::RouteObjective::Arrival,
+            "footprint" => solver::RouteObjective::Footprint,
+            _ => {
+                return Err(format!(
+                    "invalid value for '--route-objective': '{objective}'"
+                ))
             }
-            None => (),
         };
-        // let go of the lock so that the combiner doesn't run into a dead lock
-        drop(lock);
+    }
+    if let Some(n) = take_option(args, "--max-factories")? {
+        config.max_factories = parse_number("--max-factories", n)?;
+    }
+    if let Some(n) = take_option(args, "--factory-candidates")? {
+        config.factory_candidates = parse_number("--factory-candidates", n)?;
+    }
+    if let Some(n) = take_option(args, "--improvement-steps")? {
+        config.improvement_steps = parse_number("--improvement-steps", n)?;
+    }
+    if let Some(t) = take_option(args, "--initial-temperature")? {
+        config.initial_temperature = parse_number("--initial-temperature", t)?;
+    }
+    if let Some(n) = take_option(args, "--threads")? {
+        config.threads = parse_number("--threads", n)?;
+    }
+    config.validate().map_err(|e| e.to_string())?;
+    Ok(config)
+}
 
-        connect_handle.join().unwrap();
-        combine_handle.join().unwrap();
-    });
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for '{option}': '{value}'"))
+}
+
+/// Remove a flag from the arguments and return whether it was present
+fn take_flag(args: &mut Vec<&str>, flag: &str) -> bool {
+    let len = args.len();
+    args.retain(|a| *a != flag);
+    args.len() != len
+}
+
+/// Remove an option and its value from the arguments
+fn take_option<'a>(args: &mut Vec<&'a str>, option: &str) -> Result<Option<&'a str>, String> {
+    let Some(idx) = args.iter().position(|a| *a == option) else {
+        return Ok(None);
+    };
+    if idx + 1 >= args.len() {
+        return Err(format!("missing value for '{option}'"));
+    }
+    let value = args.remove(idx + 1);
+    args.remove(idx);
+    Ok(Some(value))
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &str) -> Result<T, String> {
+    let input = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+    serde_json::from_str(&input).map_err(|e| format!("{path}: {e}"))
 }
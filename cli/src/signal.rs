@@ -0,0 +1,32 @@
+//! Stop the solver on SIGINT and SIGTERM, so that the best solution found so far is still put out.
+//! A second signal exits right away.
+
+use std::sync::OnceLock;
+
+use solver::CancelToken;
+
+static CANCEL: OnceLock<CancelToken> = OnceLock::new();
+
+/// Cancel `cancel` when the process receives SIGINT or SIGTERM, only the first registered token
+/// is cancelled
+pub fn cancel_on_signals(cancel: &CancelToken) {
+    if CANCEL.set(cancel.clone()).is_err() {
+        return;
+    }
+
+    #[cfg(unix)]
+    unsafe {
+        let handler = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn handle(signal: libc::c_int) {
+    let Some(cancel) = CANCEL.get() else { return };
+    if cancel.is_cancelled() {
+        unsafe { libc::_exit(128 + signal) };
+    }
+    cancel.cancel();
+}
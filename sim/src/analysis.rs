@@ -0,0 +1,133 @@
+//! Closed form analysis of how resources flow through a layout.
+//!
+//! Every connection out of a deposit leads along a fixed path, see [`CompiledSim`]. The first
+//! resources arrive one turn per connection of the path after the deposit output them, and from
+//! then on every path delivers 3 resources per turn until the deposit is empty. Factories make as
+//! many products as they can out of what arrived, so the products made by the turn limit only
+//! depend on the resources delivered until then.
+
+use crate::{CompiledSim, Id, Node, ProductType, ResourceType, Resources, Sim};
+
+/// Resources a single mine outputs per turn
+pub const MINE_RATE: u32 = 3;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Analysis {
+    pub factories: Vec<FactoryAnalysis>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FactoryAnalysis {
+    pub id: Id,
+    pub product_type: ProductType,
+    /// Every deposit with a path to the factory
+    pub deposits: Vec<DepositInflow>,
+    /// Resources arriving per turn once all paths deliver, until the deposits are empty
+    pub rate: Resources,
+    /// Products made by the turn limit
+    pub expected_products: u32,
+    pub expected_points: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DepositInflow {
+    pub deposit_id: Id,
+    pub resource_type: ResourceType,
+    /// Number of paths from the deposit to the factory
+    pub paths: u32,
+    /// Turn at which the first resource of the deposit arrives, may be past the turn limit
+    pub first_arrival: u32,
+    /// Resources delivered by the turn limit
+    pub delivered: u32,
+}
+
+/// Analyze the flow of resources into every factory of the simulation
+pub fn analyze(sim: &Sim) -> Analysis {
+    let compiled = CompiledSim::new(sim);
+
+    let mut factory_indices = vec![None; compiled.nodes.len()];
+    let mut factories = Vec::new();
+    for (i, node) in compiled.nodes.iter().enumerate() {
+        let Node::Factory(product_type) = node else {
+            continue;
+        };
+        factory_indices[i] = Some(factories.len());
+        factories.push(FactoryAnalysis {
+            id: compiled.ids[i],
+            product_type: *product_type,
+            deposits: Vec::new(),
+            rate: Resources::default(),
+            expected_products: 0,
+            expected_points: 0,
+        });
+    }
+
+    for path in compiled.paths.iter() {
+        let Some(factory_idx) = path.end.and_then(|e| factory_indices[e as usize]) else {
+            continue;
+        };
+        let Node::Deposit(resource_type, resources) = compiled.nodes[path.deposit as usize] else {
+            unreachable!("paths start at deposits")
+        };
+        // paths served after the deposit is empty never deliver anything
+        if path.output(resources, 1) == 0 {
+            continue;
+        }
+
+        let factory = &mut factories[factory_idx];
+        factory.rate[resource_type] += MINE_RATE as u16;
+        let deposit_id = compiled.ids[path.deposit as usize];
+        let idx = match factory
+            .deposits
+            .iter()
+            .position(|d| d.deposit_id == deposit_id)
+        {
+            Some(idx) => idx,
+            None => {
+                factory.deposits.push(DepositInflow {
+                    deposit_id,
+                    resource_type,
+                    paths: 0,
+                    first_arrival: u32::MAX,
+                    delivered: 0,
+                });
+                factory.deposits.len() - 1
+            }
+        };
+        let inflow = &mut factory.deposits[idx];
+        inflow.paths += 1;
+        inflow.first_arrival = inflow.first_arrival.min(path.hops);
+        inflow.delivered += path.delivered(resources, compiled.turns);
+    }
+
+    for factory in factories.iter_mut() {
+        let mut received = [0u32; 8];
+        for d in factory.deposits.iter() {
+            received[d.resource_type as usize] += d.delivered;
+        }
+        let product = &compiled.products[factory.product_type];
+        factory.expected_products = product
+            .resources
+            .iter()
+            .zip(received)
+            .filter(|&(needed, _)| needed > 0)
+            .map(|(needed, received)| received / needed as u32)
+            .min()
+            .unwrap_or_default();
+        factory.expected_points = factory.expected_products * product.points;
+    }
+
+    Analysis { factories }
+}
+
+/// Turn at which the first resources output by a deposit arrive at the end of a path of
+/// `connectors` mines, conveyors and combiners
+pub fn arrival_turn(connectors: u32) -> u32 {
+    connectors + 1
+}
+
+/// Resources delivered by the turn limit, when `resources` arrive at `rate` per turn from
+/// `first_arrival` on
+pub fn delivered_resources(resources: u32, rate: u32, first_arrival: u32, turns: u32) -> u32 {
+    (rate * turns.saturating_sub(first_arrival)).min(resources)
+}
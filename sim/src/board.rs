@@ -536,7 +536,7 @@ pub enum CellKind {
     Inert,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Rotation {
     Right = 0,
     Down = 1,
@@ -554,7 +554,7 @@ impl Rotation {
     }
 }
 
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
 pub struct Pos {
     pub x: i8,
     pub y: i8,
@@ -635,7 +635,7 @@ pub const fn pos(x: i8, y: i8) -> Pos {
     Pos { x, y }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Connection {
     /// Output cell - input of the connection
     pub output_id: Id,
@@ -663,8 +663,9 @@ pub fn remove_building(sim: &mut Sim, id: Id) -> Building {
         _ => (false, false),
     };
 
-    for y in 0..sim.board.height {
-        for x in 0..sim.board.width {
+    let (min, max) = building_bounds(&building);
+    for y in min.y.max(0)..=max.y.min(sim.board.height - 1) {
+        for x in min.x.max(0)..=max.x.min(sim.board.width - 1) {
             let pos = pos(x, y);
             let Some(c) = sim.board[pos] else { continue };
             if c.id != id {
@@ -738,6 +739,21 @@ pub fn remove_building(sim: &mut Sim, id: Id) -> Building {
     building
 }
 
+/// Number of cells the building covers on its own, a conveyor crossing another conveyor shares
+/// the crossed cell with it and the cell counts for the conveyor placed first
+pub fn covered_cells(sim: &Sim, id: Id) -> u32 {
+    let (min, max) = building_bounds(&sim.buildings[id]);
+    let mut cells = 0;
+    for y in min.y.max(0)..=max.y.min(sim.board.height - 1) {
+        for x in min.x.max(0)..=max.x.min(sim.board.width - 1) {
+            if sim.board[pos(x, y)].is_some_and(|c| c.id == id) {
+                cells += 1;
+            }
+        }
+    }
+    cells
+}
+
 pub fn place_building(sim: &mut Sim, building: Building) -> crate::Result<Id> {
     let id = sim.buildings.push(building);
 
@@ -848,12 +864,17 @@ pub fn place_building(sim: &mut Sim, building: Building) -> crate::Result<Id> {
 
     // cleanup if placing the building failed
     if res.is_err() {
-        sim.buildings.values[id.0 as usize] = None;
-
-        for c in sim.board.cells.iter_mut() {
-            if let Some(cell) = c {
-                if cell.id == id {
-                    *c = None;
+        let building = sim.buildings.values[id.0 as usize]
+            .take()
+            .expect("building to be pushed");
+
+        let (min, max) = building_bounds(&building);
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                if let Some(Some(cell)) = sim.board.get(pos(x, y)) {
+                    if cell.id == id {
+                        sim.board[pos(x, y)] = None;
+                    }
                 }
             }
         }
@@ -865,6 +886,31 @@ pub fn place_building(sim: &mut Sim, building: Building) -> crate::Result<Id> {
     res.and(Ok(id))
 }
 
+/// The first and last cell covered by a building
+fn building_bounds(building: &Building) -> (Pos, Pos) {
+    fn cell_bounds(pos: Pos, cells: &[(Pos, CellKind)]) -> (Pos, Pos) {
+        let mut min = pos;
+        let mut max = pos;
+        for &(p, _) in cells {
+            min = Pos::new(min.x.min(pos.x + p.x), min.y.min(pos.y + p.y));
+            max = Pos::new(max.x.max(pos.x + p.x), max.y.max(pos.y + p.y));
+        }
+        (min, max)
+    }
+
+    match building {
+        Building::Deposit(d) => (d.pos, d.pos + (d.width as i8 - 1, d.height as i8 - 1)),
+        Building::Obstacle(o) => (o.pos, o.pos + (o.width as i8 - 1, o.height as i8 - 1)),
+        Building::Mine(m) => cell_bounds(m.pos, &MINE_CELLS[m.rotation as usize]),
+        Building::Conveyor(c) if c.big => {
+            cell_bounds(c.pos, &BIG_CONVEYOR_CELLS[c.rotation as usize])
+        }
+        Building::Conveyor(c) => cell_bounds(c.pos, &SMALL_CONVEYOR_CELLS[c.rotation as usize]),
+        Building::Combiner(c) => cell_bounds(c.pos, &COMBINER_CELLS[c.rotation as usize]),
+        Building::Factory(f) => (f.pos, f.pos + (FACTORY_SIZE - 1, FACTORY_SIZE - 1)),
+    }
+}
+
 fn place_cell(sim: &mut Sim, pos: impl Into<Pos>, cell: Cell) -> crate::Result<()> {
     let pos = pos.into();
 
@@ -85,6 +85,10 @@ impl Buildings {
         }
     }
 
+    pub fn get(&self, id: Id) -> Option<&Building> {
+        self.values.get(id.0 as usize).and_then(|b| b.as_ref())
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = (Id, &Building)> {
         self.values
             .iter()
@@ -100,7 +104,7 @@ impl Buildings {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Building {
     Deposit(Deposit),
     Obstacle(Obstacle),
@@ -110,7 +114,7 @@ pub enum Building {
     Factory(Factory),
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Deposit {
     pub pos: Pos,
     pub width: u8,
@@ -133,7 +137,7 @@ impl Deposit {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Obstacle {
     pub pos: Pos,
     pub width: u8,
@@ -150,7 +154,7 @@ impl Obstacle {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Mine {
     pub pos: Pos,
     pub rotation: Rotation,
@@ -165,7 +169,7 @@ impl Mine {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Conveyor {
     pub pos: Pos,
     pub rotation: Rotation,
@@ -182,7 +186,7 @@ impl Conveyor {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Combiner {
     pub pos: Pos,
     pub rotation: Rotation,
@@ -197,7 +201,7 @@ impl Combiner {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Factory {
     pub pos: Pos,
     pub product_type: ProductType,
@@ -257,7 +261,7 @@ impl Product {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum ProductType {
     Type0 = 0,
     Type1 = 1,
@@ -269,7 +273,7 @@ pub enum ProductType {
     Type7 = 7,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum ResourceType {
     Type0 = 0,
     Type1 = 1,
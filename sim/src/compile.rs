@@ -0,0 +1,339 @@
+//! A flat representation of a simulation for fast runs.
+//!
+//! Buildings are numbered densely and connections refer to them by index, so stepping through the
+//! turns doesn't need any lookups by [`Id`]. Connectors output everything they hold into their
+//! first outgoing connection, so every connection out of a deposit leads along a fixed path, and
+//! every resource on it needs one turn per connection to reach the end of the path. If no path runs
+//! in a circle, the resources a factory receives by any turn follow in closed form from the output
+//! of the deposits, so the score is computed without stepping through the turns at all.
+
+use crate::{
+    Building, Connection, Id, ProductType, Products, ResourceType, Resources, Sim, SimRun,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Node {
+    Deposit(ResourceType, u16),
+    Connector,
+    Factory(ProductType),
+}
+
+/// The path resources take out of a deposit through one of its connections
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct DepositPath {
+    pub(crate) deposit: u32,
+    /// Index of the connection among the outgoing connections of the deposit
+    order: u16,
+    /// Number of connections out of the deposit, which are served in order every turn
+    outputs: u16,
+    /// Node at the end of the path, `None` if the path runs in a circle
+    pub(crate) end: Option<u32>,
+    /// Number of connections leading to the end of the path, which is also the number of turns
+    /// resources take to get there
+    pub(crate) hops: u32,
+}
+
+impl DepositPath {
+    /// Resources the deposit outputs into the path during the first `turns` turns
+    pub(crate) fn output(&self, resources: u16, turns: u32) -> u32 {
+        let per_turn = 3 * self.outputs as u32;
+        let before = 3 * self.order as u32;
+        let resources = resources as u32;
+
+        // turns in which the path gets the full 3 resources, followed by one with the rest
+        let full = match resources.checked_sub(before + 3) {
+            Some(r) => r / per_turn + 1,
+            None => 0,
+        };
+        if turns <= full {
+            3 * turns
+        } else {
+            3 * full + resources.saturating_sub(per_turn * full + before).min(3)
+        }
+    }
+
+    /// Resources that reach the end of the path before the turn limit
+    pub(crate) fn delivered(&self, resources: u16, turns: u32) -> u32 {
+        self.output(resources, turns.saturating_sub(self.hops))
+    }
+
+    /// Number of turns in which the deposit outputs any resources into the path
+    fn output_turns(&self, resources: u16) -> u32 {
+        let per_turn = 3 * self.outputs as u32;
+        let before = 3 * self.order as u32;
+        (resources as u32).saturating_sub(before).div_ceil(per_turn)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompiledSim {
+    pub(crate) products: Products,
+    pub(crate) turns: u32,
+    pub(crate) nodes: Vec<Node>,
+    /// Id of the building of every node
+    pub(crate) ids: Vec<Id>,
+    /// Output and input node of every connection, in the order of `Sim::connections`
+    connections: Vec<(u32, u32)>,
+    /// Paths out of all deposits, in the order of their connections
+    pub(crate) paths: Vec<DepositPath>,
+    /// Whether no path runs in a circle and every product needs resources
+    analytic: bool,
+}
+
+impl CompiledSim {
+    pub fn new(sim: &Sim) -> Self {
+        Self::compile(sim, sim.buildings.iter(), sim.connections.iter())
+    }
+
+    /// Compile the given buildings and connections with the products and turns of `sim`, the
+    /// connections must only link the given buildings
+    pub(crate) fn compile<'a>(
+        sim: &Sim,
+        buildings: impl Iterator<Item = (Id, &'a Building)>,
+        connections: impl Iterator<Item = &'a Connection>,
+    ) -> Self {
+        let mut indices = Vec::new();
+        let mut nodes = Vec::new();
+        let mut ids = Vec::new();
+        for (id, b) in buildings {
+            let node = match b {
+                Building::Deposit(d) => Node::Deposit(d.resource_type, d.resources()),
+                Building::Mine(_) | Building::Conveyor(_) | Building::Combiner(_) => {
+                    Node::Connector
+                }
+                Building::Factory(f) => Node::Factory(f.product_type),
+                Building::Obstacle(_) => continue,
+            };
+            if indices.len() <= id.0 as usize {
+                indices.resize(id.0 as usize + 1, u32::MAX);
+            }
+            indices[id.0 as usize] = nodes.len() as u32;
+            nodes.push(node);
+            ids.push(id);
+        }
+        let connections = connections
+            .map(|c| {
+                (
+                    indices[c.output_id.0 as usize],
+                    indices[c.input_id.0 as usize],
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut compiled = Self {
+            products: sim.products.clone(),
+            turns: sim.turns,
+            nodes,
+            ids,
+            connections,
+            paths: Vec::new(),
+            analytic: false,
+        };
+        compiled.paths = compiled.trace_paths();
+        let circles = compiled.paths.iter().any(|p| p.end.is_none());
+        let no_resources = compiled.nodes.iter().any(|n| match n {
+            Node::Factory(p) => compiled.products[*p].resources.is_empty(),
+            _ => false,
+        });
+        compiled.analytic = !circles && !no_resources;
+        compiled
+    }
+
+    /// Whether the score can be computed without stepping through the turns
+    pub fn is_analytic(&self) -> bool {
+        self.analytic
+    }
+
+    pub fn run(&self) -> SimRun {
+        if self.analytic {
+            self.run_analytic()
+        } else {
+            self.run_stepped()
+        }
+    }
+
+    /// Follow the connections out of every deposit. Paths that run in a circle and products that
+    /// can be made out of nothing can only be handled by stepping through the turns.
+    fn trace_paths(&self) -> Vec<DepositPath> {
+        // connectors only output into their first connection
+        let mut next = vec![None; self.nodes.len()];
+        for &(output, input) in self.connections.iter() {
+            if self.nodes[output as usize] == Node::Connector {
+                next[output as usize].get_or_insert(input);
+            }
+        }
+
+        let mut outputs = vec![0u16; self.nodes.len()];
+        for &(output, _) in self.connections.iter() {
+            outputs[output as usize] += 1;
+        }
+
+        let mut order = vec![0u16; self.nodes.len()];
+        let mut paths = Vec::new();
+        for &(deposit, input) in self.connections.iter() {
+            let Node::Deposit(_, _) = self.nodes[deposit as usize] else {
+                continue;
+            };
+            let mut end = Some(input);
+            let mut hops = 1;
+            while let Some(n) = end.and_then(|e| next[e as usize]) {
+                end = Some(n);
+                hops += 1;
+                if hops as usize > self.nodes.len() {
+                    end = None;
+                }
+            }
+            paths.push(DepositPath {
+                deposit,
+                order: order[deposit as usize],
+                outputs: outputs[deposit as usize],
+                end,
+                hops,
+            });
+            order[deposit as usize] += 1;
+        }
+        paths
+    }
+
+    /// Score the resources every factory receives by the turn limit. The products a factory has
+    /// made only grow with the turns, so the turn of its last product is found by bisection.
+    fn run_analytic(&self) -> SimRun {
+        let mut run = SimRun {
+            rounds: 0,
+            points: 0,
+            at_turn: 0,
+        };
+        for path in self.paths.iter() {
+            let Node::Deposit(_, resources) = self.nodes[path.deposit as usize] else {
+                unreachable!("paths start at deposits")
+            };
+            // the round after the last resources arrive at the end of the path
+            let output_turns = path.output_turns(resources).min(self.turns);
+            if output_turns > 0 {
+                run.rounds = run.rounds.max((output_turns + path.hops).min(self.turns));
+            }
+        }
+
+        for (factory, node) in self.nodes.iter().enumerate() {
+            let Node::Factory(product_type) = node else {
+                continue;
+            };
+            let product = &self.products[*product_type];
+            let paths = self
+                .paths
+                .iter()
+                .filter(|p| p.end == Some(factory as u32))
+                .collect::<Vec<_>>();
+            // products made with the resources that arrive before the turn limit
+            let made = |turns: u32| {
+                let mut received = [0u32; 8];
+                for path in paths.iter() {
+                    let Node::Deposit(resource_type, resources) = self.nodes[path.deposit as usize]
+                    else {
+                        unreachable!("paths start at deposits")
+                    };
+                    received[resource_type as usize] += path.delivered(resources, turns);
+                }
+                product
+                    .resources
+                    .iter()
+                    .zip(received)
+                    .filter(|&(needed, _)| needed > 0)
+                    .map(|(needed, received)| received / needed as u32)
+                    .min()
+                    .unwrap_or_default()
+            };
+
+            let products = made(self.turns);
+            if products == 0 {
+                continue;
+            }
+            run.points += products * product.points;
+            let (mut low, mut high) = (1, self.turns);
+            while low < high {
+                let mid = low + (high - low) / 2;
+                if made(mid) < products {
+                    low = mid + 1;
+                } else {
+                    high = mid;
+                }
+            }
+            run.at_turn = run.at_turn.max(low);
+        }
+
+        run
+    }
+
+    /// Step through the turns, equivalent to [`crate::run`]
+    pub fn run_stepped(&self) -> SimRun {
+        let mut run = SimRun {
+            rounds: 0,
+            points: 0,
+            at_turn: 0,
+        };
+        let mut remaining = self
+            .nodes
+            .iter()
+            .map(|n| match n {
+                Node::Deposit(_, resources) => *resources,
+                _ => 0,
+            })
+            .collect::<Vec<_>>();
+        let mut held = vec![Resources::default(); self.nodes.len()];
+        let mut in_transit = vec![Resources::default(); self.connections.len()];
+
+        while run.rounds < self.turns {
+            let mut unchanged = true;
+
+            // start of the round
+            for (&(_, input), resources) in self.connections.iter().zip(in_transit.iter_mut()) {
+                let res = std::mem::take(resources);
+                unchanged &= res.is_empty();
+                held[input as usize] += res;
+            }
+
+            // end of the round
+            for (&(output, _), resources) in self.connections.iter().zip(in_transit.iter_mut()) {
+                let output = output as usize;
+                *resources = match self.nodes[output] {
+                    Node::Deposit(resource_type, _) => {
+                        let num = remaining[output].min(3);
+                        remaining[output] -= num;
+                        let mut res = Resources::default();
+                        res[resource_type] += num;
+                        res
+                    }
+                    Node::Connector => std::mem::take(&mut held[output]),
+                    Node::Factory(_) => unreachable!("Factories cannot output resources"),
+                };
+                unchanged &= resources.is_empty();
+            }
+            for (node, resources) in self.nodes.iter().zip(held.iter_mut()) {
+                let Node::Factory(product_type) = node else {
+                    continue;
+                };
+                let product = &self.products[*product_type];
+                if !resources.has_at_least(&product.resources) {
+                    continue;
+                }
+                let num_products = (*resources / product.resources)
+                    .iter()
+                    .min()
+                    .unwrap_or_default();
+                if num_products > 0 {
+                    *resources -= product.resources * Resources::new([num_products; 8]);
+                    run.points += product.points * num_products as u32;
+                    run.at_turn = run.rounds + 1;
+                    unchanged = false;
+                }
+            }
+
+            if unchanged {
+                break;
+            }
+            run.rounds += 1;
+        }
+
+        run
+    }
+}
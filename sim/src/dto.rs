@@ -1,8 +1,10 @@
+use core::fmt;
+
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    place_building, pos, Board, Building, Combiner, Conveyor, Deposit, Error, Factory, IoError,
-    Mine, Obstacle, ProductType, Products, ResourceType, Resources, Rotation, Sim,
+    place_building, pos, Board, Building, CellKind, Combiner, Conveyor, Deposit, Error, Factory,
+    Id, IoError, Mine, Obstacle, ProductType, Products, ResourceType, Resources, Rotation, Sim,
 };
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -16,26 +18,49 @@ pub struct Task {
 }
 
 impl TryFrom<&Task> for Sim {
-    type Error = Error;
+    type Error = TaskError;
 
     fn try_from(task: &Task) -> Result<Self, Self::Error> {
         let products = Products::default();
         let board = Board::new(task.width, task.height);
         let mut sim = Sim::new(products, board, task.turns, task.time);
 
-        for p in task.products.iter() {
-            let product_type = ProductType::try_from(p.subtype)?;
+        for (index, p) in task.products.iter().enumerate() {
+            let product_type = ProductType::try_from(p.subtype)
+                .map_err(|e| TaskError::Product(index, e.into()))?;
             sim.products[product_type] = crate::Product::new(Resources::new(p.resources), p.points);
         }
 
-        for o in task.objects.iter() {
-            place_building(&mut sim, o.try_into()?)?;
+        for (index, o) in task.objects.iter().enumerate() {
+            Building::try_from(o)
+                .map_err(Error::from)
+                .and_then(|b| place_building(&mut sim, b))
+                .map_err(|error| TaskError::Object(ObjectError { index, error }))?;
         }
 
         Ok(sim)
     }
 }
 
+/// An error caused by a product or an object of a [`Task`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TaskError {
+    /// Index of the product inside the task
+    Product(usize, Error),
+    Object(ObjectError),
+}
+
+impl std::error::Error for TaskError {}
+
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskError::Product(index, error) => write!(f, "product {index}: {error}"),
+            TaskError::Object(e) => write!(f, "{e}"),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TaskObject {
     #[serde(rename = "type")]
@@ -85,7 +110,7 @@ impl TryFrom<&TaskObject> for Building {
             )),
             ObjectKind::Conveyor => {
                 if o.subtype >= 8 {
-                    return Err(IoError::UnknownMineSubtype(o.subtype));
+                    return Err(IoError::UnknownConveyorSubtype(o.subtype));
                 }
                 Building::Conveyor(Conveyor::new(
                     pos,
@@ -166,6 +191,137 @@ impl TryFrom<u8> for ResourceType {
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Solution(Vec<SolutionObject>);
 
+impl Solution {
+    pub fn new(objects: Vec<SolutionObject>) -> Self {
+        Self(objects)
+    }
+
+    pub fn objects(&self) -> &[SolutionObject] {
+        &self.0
+    }
+
+    /// The buildings of `sim` that aren't prebuilt buildings of the `task` it was built from,
+    /// those are already on the board and can't be placed a second time
+    pub fn added(task: &Sim, sim: &Sim) -> Self {
+        let objects = sim
+            .buildings
+            .iter()
+            .filter(|&(id, b)| task.buildings.get(id) != Some(b))
+            .filter_map(|(_, b)| SolutionObject::try_from(b).ok())
+            .collect();
+        Solution(objects)
+    }
+
+    /// Place all objects of this solution, stopping at the first one that can't be placed
+    pub fn place(&self, sim: &mut Sim) -> Result<(), ObjectError> {
+        for (index, o) in self.0.iter().enumerate() {
+            let res = Building::try_from(o)
+                .map_err(Error::from)
+                .and_then(|b| place_building(sim, b));
+            if let Err(error) = res {
+                return Err(ObjectError { index, error });
+            }
+        }
+        Ok(())
+    }
+
+    /// Place all objects of this solution, skipping the ones that can't be placed, and report
+    /// every conflict that was found
+    pub fn place_all(&self, sim: &mut Sim) -> Vec<Conflict> {
+        let mut placed = Vec::with_capacity(self.0.len());
+        let mut conflicts = Vec::new();
+        for (index, o) in self.0.iter().enumerate() {
+            let res = Building::try_from(o)
+                .map_err(Error::from)
+                .and_then(|b| place_building(sim, b));
+            match res {
+                Ok(id) => placed.push((id, index)),
+                Err(error) => {
+                    let other = conflicting_building(sim, &error);
+                    let other_index = other.and_then(|other| {
+                        placed.iter().find(|(id, _)| *id == other).map(|(_, i)| *i)
+                    });
+                    conflicts.push(Conflict {
+                        index,
+                        error,
+                        other,
+                        other_index,
+                    });
+                }
+            }
+        }
+        conflicts
+    }
+}
+
+/// Find the already placed building that caused the error
+fn conflicting_building(sim: &Sim, error: &Error) -> Option<Id> {
+    let pos = error.pos()?;
+
+    if let Error::MultipleIngresses(_) = error {
+        if let Some(c) = sim.connections.iter().find(|c| c.output_pos == pos) {
+            return Some(c.input_id);
+        }
+    }
+
+    // the cell is occupied by the other building
+    if let Some(Some(cell)) = sim.board.get(pos) {
+        return Some(cell.id);
+    }
+
+    // the cell belonged to the building that couldn't be placed, look for the other end of the
+    // connection
+    let other_kind = match error {
+        Error::MineEgress(_) | Error::MultipleIngresses(_) => CellKind::Input,
+        Error::DepositEgress(_) => CellKind::Output,
+        _ => return None,
+    };
+    [(0, -1), (-1, 0), (0, 1), (1, 0)]
+        .into_iter()
+        .filter_map(|offset| sim.board.get(pos + offset).flatten())
+        .find(|cell| cell.kind == other_kind)
+        .map(|cell| cell.id)
+}
+
+/// An object of a [`Solution`] that couldn't be placed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conflict {
+    /// Index of the object inside the solution
+    pub index: usize,
+    pub error: Error,
+    /// The already placed building the object clashes with
+    pub other: Option<Id>,
+    /// Index of `other` inside the solution, if it was placed by the solution
+    pub other_index: Option<usize>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "object {}: {}", self.index, self.error)?;
+        match (self.other_index, self.other) {
+            (Some(i), _) => write!(f, " (conflicts with object {i})"),
+            (None, Some(id)) => write!(f, " (conflicts with task object {})", id.0),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// An error caused by an object of a [`Task`] or a [`Solution`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjectError {
+    /// Index of the object inside the task or solution
+    pub index: usize,
+    pub error: Error,
+}
+
+impl std::error::Error for ObjectError {}
+
+impl fmt::Display for ObjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "object {}: {}", self.index, self.error)
+    }
+}
+
 impl From<&Sim> for Solution {
     fn from(sim: &Sim) -> Self {
         let objects = sim
@@ -187,6 +343,22 @@ pub struct SolutionObject {
     pub y: i8,
 }
 
+impl TryFrom<&SolutionObject> for Building {
+    type Error = IoError;
+
+    fn try_from(o: &SolutionObject) -> Result<Self, IoError> {
+        let task_object = TaskObject {
+            kind: o.kind.clone(),
+            subtype: o.subtype,
+            x: o.x,
+            y: o.y,
+            width: 0,
+            height: 0,
+        };
+        Building::try_from(&task_object)
+    }
+}
+
 impl TryFrom<&Building> for SolutionObject {
     type Error = ();
 
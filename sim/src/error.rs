@@ -16,6 +16,20 @@ pub enum Error {
 
 impl std::error::Error for Error {}
 
+impl Error {
+    /// The cell that caused the error
+    pub fn pos(&self) -> Option<Pos> {
+        match self {
+            Error::OutOfBounds(pos)
+            | Error::Interseciton(pos)
+            | Error::MineEgress(pos)
+            | Error::DepositEgress(pos)
+            | Error::MultipleIngresses(pos) => Some(*pos),
+            Error::Io(_) => None,
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -0,0 +1,181 @@
+//! Incremental evaluation of simulations that differ by a few buildings.
+//!
+//! Resources only move along connections, so buildings that aren't linked by a chain of
+//! connections can't influence each other. The [`Evaluator`] splits the connection graph into
+//! these components and caches the run of every component, so after adding or removing a building
+//! only the components containing changed buildings are simulated again. Components are keyed by
+//! a hash of their buildings and connections, independently of the ids of the buildings, so they
+//! are also recognized after being copied to another simulation. The buildings are only copied
+//! into the cache when a component is simulated, and compared in place when it's looked up.
+//!
+//! The combined run is the same as the one of [`crate::run`]: points of the components add up,
+//! and both the turn of the last product and the number of rounds are the latest of all components,
+//! since a component that stopped changing never changes again.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use crate::{Building, CompiledSim, Connection, Id, Products, Sim, SimRun};
+
+/// Cached component runs after which the cache is cleared, to bound the memory usage
+const MAX_CACHED_COMPONENTS: usize = 1 << 14;
+
+/// Buildings linked by connections, as found in a simulation
+#[derive(Clone, Debug, Default)]
+struct Component {
+    /// Ids of the buildings in the order of `Sim::buildings`
+    ids: Vec<Id>,
+    /// Connections in the order of `Sim::connections`, with ids that are indices into `ids`
+    connections: Vec<Connection>,
+}
+
+impl Component {
+    fn hash(&self, sim: &Sim) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for &id in self.ids.iter() {
+            sim.buildings[id].hash(&mut hasher);
+        }
+        self.connections.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+#[derive(Clone, Debug)]
+struct CachedComponent {
+    buildings: Vec<Building>,
+    connections: Vec<Connection>,
+    run: SimRun,
+}
+
+impl CachedComponent {
+    /// Whether the component is the cached one, hashes of different components may collide
+    fn matches(&self, sim: &Sim, component: &Component) -> bool {
+        self.buildings.len() == component.ids.len()
+            && self.connections == component.connections
+            && self
+                .buildings
+                .iter()
+                .zip(component.ids.iter())
+                .all(|(b, &id)| *b == sim.buildings[id])
+    }
+}
+
+/// Evaluates simulations of the same task, reusing the runs of unchanged components
+#[derive(Clone, Debug, Default)]
+pub struct Evaluator {
+    products: Products,
+    turns: u32,
+    /// Runs of components by the hash of the component
+    cache: HashMap<u64, CachedComponent>,
+    /// Union find forest over building ids, reused between evaluations
+    parents: Vec<u16>,
+}
+
+impl Evaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of cached component runs
+    pub fn cached(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Run the simulation, equivalent to [`crate::run`]
+    pub fn run(&mut self, sim: &Sim) -> SimRun {
+        if self.turns != sim.turns || self.products != sim.products {
+            self.turns = sim.turns;
+            self.products = sim.products.clone();
+            self.cache.clear();
+        }
+        if self.cache.len() > MAX_CACHED_COMPONENTS {
+            self.cache.clear();
+        }
+
+        self.parents.clear();
+        self.parents.extend(0..sim.buildings.values.len() as u16);
+        for c in sim.connections.iter() {
+            let a = self.find(c.output_id.0);
+            let b = self.find(c.input_id.0);
+            self.parents[a as usize] = b;
+        }
+
+        // group the buildings linked by connections and their connections by component
+        let mut component_indices = vec![None; self.parents.len()];
+        let mut local_ids = vec![Id(0); self.parents.len()];
+        let mut components: Vec<Component> = Vec::new();
+        for c in sim.connections.iter() {
+            let root = self.find(c.output_id.0) as usize;
+            component_indices[root].get_or_insert_with(|| {
+                components.push(Component::default());
+                components.len() - 1
+            });
+        }
+        for (id, _) in sim.buildings.iter() {
+            let root = self.find(id.0) as usize;
+            if let Some(idx) = component_indices[root] {
+                let ids = &mut components[idx].ids;
+                local_ids[id.0 as usize] = Id(ids.len() as u16);
+                ids.push(id);
+            }
+        }
+        for c in sim.connections.iter() {
+            let root = self.find(c.output_id.0) as usize;
+            let idx = component_indices[root].expect("component of the connection");
+            components[idx].connections.push(Connection::new(
+                local_ids[c.output_id.0 as usize],
+                c.output_pos,
+                local_ids[c.input_id.0 as usize],
+                c.input_pos,
+            ));
+        }
+
+        let mut run = SimRun {
+            rounds: 0,
+            points: 0,
+            at_turn: 0,
+        };
+        for component in components {
+            let hash = component.hash(sim);
+            let component_run = match self.cache.get(&hash) {
+                Some(cached) if cached.matches(sim, &component) => cached.run.clone(),
+                _ => {
+                    let buildings = component
+                        .ids
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &id)| (Id(i as u16), &sim.buildings[id]));
+                    let r =
+                        CompiledSim::compile(sim, buildings, component.connections.iter()).run();
+                    let cached = CachedComponent {
+                        buildings: component
+                            .ids
+                            .iter()
+                            .map(|&id| sim.buildings[id].clone())
+                            .collect(),
+                        connections: component.connections,
+                        run: r.clone(),
+                    };
+                    self.cache.insert(hash, cached);
+                    r
+                }
+            };
+            run.rounds = run.rounds.max(component_run.rounds);
+            run.points += component_run.points;
+            run.at_turn = run.at_turn.max(component_run.at_turn);
+        }
+
+        run
+    }
+
+    fn find(&mut self, mut idx: u16) -> u16 {
+        while self.parents[idx as usize] != idx {
+            // path halving
+            let parent = self.parents[idx as usize];
+            self.parents[idx as usize] = self.parents[parent as usize];
+            idx = parent;
+        }
+        idx
+    }
+}
@@ -1,15 +1,28 @@
+use core::fmt;
 use std::collections::HashMap;
 
+pub use analysis::*;
 pub use board::*;
 pub use building::*;
+pub use compile::*;
 pub use error::*;
+pub use evaluate::*;
+pub use render::*;
+pub use svg::*;
+pub use trace::*;
 
+mod analysis;
 mod board;
 mod building;
+mod compile;
 pub mod dto;
 mod error;
+mod evaluate;
+mod render;
+mod svg;
 #[cfg(test)]
 mod test;
+mod trace;
 
 enum ResourceContainer {
     Deposit(ResourceType, u16),
@@ -33,6 +46,18 @@ impl ResourceContainer {
         }
     }
 
+    fn resources(&self) -> Resources {
+        match self {
+            Self::Deposit(resource_type, resources) => {
+                let mut res = Resources::default();
+                res[*resource_type] = *resources;
+                res
+            }
+            Self::Connector(resources) => *resources,
+            Self::Factory(_, resources) => *resources,
+        }
+    }
+
     fn input_resources(&mut self, res: Resources) {
         match self {
             Self::Deposit(_, _) => unreachable!("Deposits cannot input resources"),
@@ -55,6 +80,16 @@ pub struct SimRun {
     pub at_turn: u32,
 }
 
+impl fmt::Display for SimRun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} points at turn {} ({} rounds)",
+            self.points, self.at_turn, self.rounds
+        )
+    }
+}
+
 impl Ord for SimRun {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         Ord::cmp(&self.points, &other.points).then(Ord::cmp(&other.at_turn, &self.at_turn))
@@ -68,13 +103,34 @@ impl PartialOrd for SimRun {
 }
 
 pub fn run(sim: &Sim) -> SimRun {
+    CompiledSim::new(sim).run()
+}
+
+/// Run the simulation and record the contents of all containers and connections after every turn
+pub fn run_traced(sim: &Sim) -> Trace {
+    let mut turns = Vec::new();
+    let run = simulate(
+        sim,
+        sim.buildings.iter(),
+        sim.connections.iter(),
+        Some(&mut turns),
+    );
+    Trace { run, turns }
+}
+
+/// Simulate the given buildings and connections with the products and turns of `sim`, the
+/// connections must only link the given buildings
+fn simulate<'a>(
+    sim: &Sim,
+    buildings: impl Iterator<Item = (Id, &'a Building)>,
+    connections: impl Iterator<Item = &'a Connection>,
+    mut trace: Option<&mut Vec<TurnTrace>>,
+) -> SimRun {
     let mut points = 0;
     let mut turn = 0;
     let mut at_turn = 0;
 
-    let mut containers = sim
-        .buildings
-        .iter()
+    let mut containers = buildings
         .filter_map(|(id, b)| {
             let container = match b {
                 Building::Deposit(d) => ResourceContainer::Deposit(d.resource_type, d.resources()),
@@ -90,9 +146,7 @@ pub fn run(sim: &Sim) -> SimRun {
         })
         .collect::<HashMap<_, _>>();
 
-    let mut connections = sim
-        .connections
-        .iter()
+    let mut connections = connections
         .map(|c| ResourceConnection {
             output_id: c.output_id,
             resources: Resources::default(),
@@ -121,7 +175,8 @@ pub fn run(sim: &Sim) -> SimRun {
             con.resources = building_a.output_resources();
             unchanged &= con.resources.is_empty();
         }
-        for (_, c) in containers.iter_mut() {
+        let mut products = Vec::new();
+        for (id, c) in containers.iter_mut() {
             let ResourceContainer::Factory(product_type, resources) = c else { continue };
             let product = &sim.products[*product_type];
             if resources.has_at_least(&product.resources) {
@@ -135,6 +190,15 @@ pub fn run(sim: &Sim) -> SimRun {
                     points += product.points * num_products as u32;
                     at_turn = turn + 1;
                     unchanged = false;
+
+                    if trace.is_some() {
+                        products.push(ProductionTrace {
+                            factory_id: *id,
+                            product_type: *product_type,
+                            count: num_products,
+                            points: product.points * num_products as u32,
+                        });
+                    }
                 }
             }
         }
@@ -143,6 +207,33 @@ pub fn run(sim: &Sim) -> SimRun {
             break;
         }
 
+        if let Some(trace) = trace.as_deref_mut() {
+            let mut container_traces = containers
+                .iter()
+                .map(|(id, c)| ContainerTrace {
+                    id: *id,
+                    resources: c.resources(),
+                })
+                .collect::<Vec<_>>();
+            container_traces.sort_by_key(|c| c.id.0);
+            products.sort_by_key(|p| p.factory_id.0);
+
+            trace.push(TurnTrace {
+                turn,
+                points,
+                containers: container_traces,
+                connections: connections
+                    .iter()
+                    .map(|c| ConnectionTrace {
+                        output_id: c.output_id,
+                        input_id: c.input_id,
+                        resources: c.resources,
+                    })
+                    .collect(),
+                products,
+            });
+        }
+
         turn += 1;
     }
 
@@ -0,0 +1,114 @@
+//! Every cell is rendered as three characters: the kind of building, the kind of cell, using the
+//! same notation as in [`crate::board`], and a detail.
+//!
+//! building:
+//! deposit:         D
+//! obstacle:        X
+//! mine:            M
+//! small conveyor:  c
+//! big conveyor:    C
+//! combiner:        B
+//! factory:         F
+//!
+//! detail:
+//! deposit:                    resource type
+//! factory:                    product type
+//! mine, conveyor, combiner:   rotation (> v < ^)
+
+use core::fmt;
+
+use crate::{pos, Building, CellKind, Rotation, Sim};
+
+/// Render a [`Sim`] with all its buildings, see the module documentation for the notation
+pub struct Render<'a> {
+    sim: &'a Sim,
+    color: bool,
+}
+
+impl<'a> Render<'a> {
+    pub fn new(sim: &'a Sim) -> Self {
+        Self { sim, color: true }
+    }
+
+    /// Use ANSI escape codes to color cells by their kind, enabled by default
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+}
+
+impl fmt::Display for Render<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let board = &self.sim.board;
+
+        self.write_header(f, "    ")?;
+        for x in 0..board.width {
+            self.write_header(f, &format!("{x:^3}"))?;
+        }
+        for y in 0..board.height {
+            f.write_str("\n")?;
+            self.write_header(f, &format!("{y:>3} "))?;
+            for x in 0..board.width {
+                let p = pos(x, y);
+                let Some(cell) = board[p] else {
+                    f.write_str(" . ")?;
+                    continue;
+                };
+
+                let building = &self.sim.buildings[cell.id];
+                let (letter, origin, detail) = match building {
+                    Building::Deposit(d) => ('D', d.pos, digit(d.resource_type as u8)),
+                    Building::Obstacle(o) => ('X', o.pos, ' '),
+                    Building::Mine(m) => ('M', m.pos, arrow(m.rotation)),
+                    Building::Conveyor(c) if c.big => ('C', c.pos, arrow(c.rotation)),
+                    Building::Conveyor(c) => ('c', c.pos, arrow(c.rotation)),
+                    Building::Combiner(c) => ('B', c.pos, arrow(c.rotation)),
+                    Building::Factory(fa) => ('F', fa.pos, digit(fa.product_type as u8)),
+                };
+                let symbol = match cell.kind {
+                    _ if origin == p => '#',
+                    CellKind::Input => '+',
+                    CellKind::Output => '-',
+                    CellKind::Inert => 'o',
+                };
+
+                if self.color {
+                    let style = match (building, cell.kind) {
+                        (Building::Obstacle(_), _) => "7;90",
+                        (_, CellKind::Input) => "1;7;92",
+                        (_, CellKind::Output) => "1;7;91",
+                        (_, CellKind::Inert) => "1;7;33",
+                    };
+                    write!(f, "\x1B[{style}m{letter}{symbol}{detail}\x1B[0m")?;
+                } else {
+                    write!(f, "{letter}{symbol}{detail}")?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Render<'_> {
+    fn write_header(&self, f: &mut fmt::Formatter<'_>, text: &str) -> fmt::Result {
+        if self.color {
+            write!(f, "\x1B[1;7;94m{text}\x1B[0m")
+        } else {
+            f.write_str(text)
+        }
+    }
+}
+
+fn digit(n: u8) -> char {
+    char::from(b'0' + n)
+}
+
+fn arrow(rotation: Rotation) -> char {
+    match rotation {
+        Rotation::Right => '>',
+        Rotation::Down => 'v',
+        Rotation::Left => '<',
+        Rotation::Up => '^',
+    }
+}
@@ -0,0 +1,179 @@
+use core::fmt;
+
+use crate::{pos, Building, CellKind, Pos, Rotation, Sim};
+
+const CELL_SIZE: i32 = 20;
+
+const RESOURCE_COLORS: [&str; 8] = [
+    "#e57373", "#64b5f6", "#81c784", "#ffb74d", "#ba68c8", "#4db6ac", "#f06292", "#a1887f",
+];
+const OBSTACLE_COLOR: &str = "#616161";
+const MINE_COLOR: &str = "#8d6e63";
+const SMALL_CONVEYOR_COLOR: &str = "#b0bec5";
+const BIG_CONVEYOR_COLOR: &str = "#78909c";
+const COMBINER_COLOR: &str = "#9575cd";
+const FACTORY_COLOR: &str = "#fdd835";
+
+/// Export a [`Sim`] as an SVG image, optionally overlaid with a distance map
+pub struct Svg<'a> {
+    sim: &'a Sim,
+    overlay: Option<&'a dyn Fn(Pos) -> Option<u16>>,
+}
+
+impl<'a> Svg<'a> {
+    pub fn new(sim: &'a Sim) -> Self {
+        Self { sim, overlay: None }
+    }
+
+    /// Print the values of `overlay` into all empty cells, e.g. the distances of a distance map
+    pub fn overlay(mut self, overlay: &'a dyn Fn(Pos) -> Option<u16>) -> Self {
+        self.overlay = Some(overlay);
+        self
+    }
+}
+
+impl fmt::Display for Svg<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let board = &self.sim.board;
+        let width = board.width as i32 * CELL_SIZE;
+        let height = board.height as i32 * CELL_SIZE;
+
+        writeln!(
+            f,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" font-family="monospace" font-size="{}" text-anchor="middle" dominant-baseline="central">"#,
+            CELL_SIZE * 3 / 5
+        )?;
+        writeln!(
+            f,
+            r##"<rect width="{width}" height="{height}" fill="#fafafa"/>"##
+        )?;
+
+        // grid
+        write!(f, r##"<path stroke="#e0e0e0" stroke-width="1" d=""##)?;
+        for x in 1..board.width as i32 {
+            write!(f, "M{} 0V{height}", x * CELL_SIZE)?;
+        }
+        for y in 1..board.height as i32 {
+            write!(f, "M0 {}H{width}", y * CELL_SIZE)?;
+        }
+        writeln!(f, r#""/>"#)?;
+
+        let max_overlay = self.max_overlay();
+
+        for y in 0..board.height {
+            for x in 0..board.width {
+                let p = pos(x, y);
+                let (px, py) = (x as i32 * CELL_SIZE, y as i32 * CELL_SIZE);
+                let center = (px + CELL_SIZE / 2, py + CELL_SIZE / 2);
+
+                let Some(cell) = board[p] else {
+                    let Some(value) = self.overlay.and_then(|o| o(p)) else { continue };
+                    let opacity = 0.6 * (1.0 - value as f32 / (max_overlay as f32 + 1.0));
+                    writeln!(
+                        f,
+                        r##"<rect x="{px}" y="{py}" width="{CELL_SIZE}" height="{CELL_SIZE}" fill="#1e88e5" fill-opacity="{opacity:.2}"/>"##
+                    )?;
+                    writeln!(
+                        f,
+                        r##"<text x="{}" y="{}" font-size="{}" fill="#0d47a1">{value}</text>"##,
+                        center.0,
+                        center.1,
+                        CELL_SIZE * 2 / 5
+                    )?;
+                    continue;
+                };
+
+                let building = &self.sim.buildings[cell.id];
+                let (color, origin) = match building {
+                    Building::Deposit(d) => (RESOURCE_COLORS[d.resource_type as usize], d.pos),
+                    Building::Obstacle(o) => (OBSTACLE_COLOR, o.pos),
+                    Building::Mine(m) => (MINE_COLOR, m.pos),
+                    Building::Conveyor(c) if c.big => (BIG_CONVEYOR_COLOR, c.pos),
+                    Building::Conveyor(c) => (SMALL_CONVEYOR_COLOR, c.pos),
+                    Building::Combiner(c) => (COMBINER_COLOR, c.pos),
+                    Building::Factory(fa) => (FACTORY_COLOR, fa.pos),
+                };
+                let opacity = match cell.kind {
+                    CellKind::Inert => 1.0,
+                    CellKind::Input | CellKind::Output => 0.6,
+                };
+                writeln!(
+                    f,
+                    r#"<rect x="{px}" y="{py}" width="{CELL_SIZE}" height="{CELL_SIZE}" fill="{color}" fill-opacity="{opacity}"/>"#
+                )?;
+
+                // outline the building
+                let mut outline = String::new();
+                let id = cell.id;
+                let other =
+                    |offset: (i8, i8)| board.get(p + offset).flatten().map(|c| c.id) != Some(id);
+                if other((0, -1)) {
+                    outline.push_str(&format!("M{px} {py}h{CELL_SIZE}"));
+                }
+                if other((1, 0)) {
+                    outline.push_str(&format!("M{} {py}v{CELL_SIZE}", px + CELL_SIZE));
+                }
+                if other((0, 1)) {
+                    outline.push_str(&format!("M{px} {}h{CELL_SIZE}", py + CELL_SIZE));
+                }
+                if other((-1, 0)) {
+                    outline.push_str(&format!("M{px} {py}v{CELL_SIZE}"));
+                }
+                if !outline.is_empty() {
+                    writeln!(
+                        f,
+                        r##"<path stroke="#212121" stroke-width="2" d="{outline}"/>"##
+                    )?;
+                }
+
+                let label = match building {
+                    Building::Deposit(d) if origin == p => {
+                        Some((d.resource_type as u8).to_string())
+                    }
+                    Building::Factory(fa) if origin == p => {
+                        Some(format!("P{}", fa.product_type as u8))
+                    }
+                    Building::Mine(m) if origin == p => Some(arrow(m.rotation).to_string()),
+                    Building::Conveyor(c) if origin == p => Some(arrow(c.rotation).to_string()),
+                    Building::Combiner(c) if origin == p => Some(arrow(c.rotation).to_string()),
+                    Building::Deposit(_) | Building::Obstacle(_) | Building::Factory(_) => None,
+                    _ => match cell.kind {
+                        CellKind::Input => Some("+".to_string()),
+                        CellKind::Output => Some("-".to_string()),
+                        CellKind::Inert => None,
+                    },
+                };
+                if let Some(label) = label {
+                    writeln!(
+                        f,
+                        r##"<text x="{}" y="{}" fill="#212121">{label}</text>"##,
+                        center.0, center.1
+                    )?;
+                }
+            }
+        }
+
+        f.write_str("</svg>\n")
+    }
+}
+
+impl Svg<'_> {
+    fn max_overlay(&self) -> u16 {
+        let Some(overlay) = self.overlay else { return 0 };
+        let board = &self.sim.board;
+        (0..board.height)
+            .flat_map(|y| (0..board.width).map(move |x| pos(x, y)))
+            .filter_map(overlay)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+fn arrow(rotation: Rotation) -> char {
+    match rotation {
+        Rotation::Right => '→',
+        Rotation::Down => '↓',
+        Rotation::Left => '←',
+        Rotation::Up => '↑',
+    }
+}
@@ -113,6 +113,35 @@ fn failing_to_place_conveyor_doesnt_remove_part_of_existing_conveyor_2() {
     assert_eq!(sim.board, expected);
 }
 
+#[test]
+fn removing_crossed_conveyor_restores_shared_cell() {
+    let mut sim = Sim::new(Products::default(), Board::new(10, 10), TURNS, TIME);
+
+    let building = Building::Conveyor(Conveyor::new((4, 4), Rotation::Right, false));
+    let horizontal = place_building(&mut sim, building).unwrap();
+    let building = Building::Conveyor(Conveyor::new((4, 4), Rotation::Down, false));
+    let vertical = place_building(&mut sim, building).unwrap();
+
+    // the crossed cell belongs to the conveyor placed first
+    assert_eq!(sim.board[pos(4, 4)], Some(Cell::inert(horizontal)));
+    assert_eq!(covered_cells(&sim, horizontal), 3);
+    assert_eq!(covered_cells(&sim, vertical), 2);
+
+    remove_building(&mut sim, horizontal);
+    assert_eq!(sim.board[pos(4, 4)], Some(Cell::inert(vertical)));
+    assert_eq!(covered_cells(&sim, vertical), 3);
+
+    let building = Building::Conveyor(Conveyor::new((4, 4), Rotation::Right, false));
+    let horizontal = place_building(&mut sim, building).unwrap();
+    remove_building(&mut sim, horizontal);
+
+    let mut expected = Board::new(10, 10);
+    expected[pos(4, 3)] = Some(Cell::input(vertical));
+    expected[pos(4, 4)] = Some(Cell::inert(vertical));
+    expected[pos(4, 5)] = Some(Cell::output(vertical));
+    assert_eq!(sim.board, expected);
+}
+
 #[test]
 fn deposit_mine_factory() {
     let mut products = Products::default();
@@ -496,3 +525,429 @@ fn reached_score_at_turn_9() {
         },
     );
 }
+
+#[test]
+fn trace_reached_score_at_turn_9() {
+    let input = std::fs::read_to_string("../tests/001/test.json").unwrap();
+    let task: dto::Task = serde_json::from_str(&input).unwrap();
+    let sim = Sim::try_from(&task).unwrap();
+
+    let trace = run_traced(&sim);
+    assert_eq!(trace.run, run(&sim));
+    assert_eq!(trace.turns.len(), trace.run.rounds as usize);
+
+    let deposit_id = Id(0);
+    let factory_id = Id(3);
+    let remaining = |turn: usize| trace.turns[turn].container(deposit_id).unwrap().resources;
+    assert_eq!(remaining(0), Resources::new([39, 0, 0, 0, 0, 0, 0, 0]));
+    assert_eq!(remaining(6), Resources::new([3, 0, 0, 0, 0, 0, 0, 0]));
+    assert_eq!(remaining(7), Resources::default());
+
+    let produced_at = trace
+        .turns
+        .iter()
+        .filter(|t| !t.products.is_empty())
+        .map(|t| t.turn)
+        .collect::<Vec<_>>();
+    assert_eq!(produced_at, [5, 8]);
+    assert_eq!(
+        trace.turns[8].products,
+        [ProductionTrace {
+            factory_id,
+            product_type: ProductType::Type0,
+            count: 1,
+            points: 10,
+        }]
+    );
+    assert_eq!(trace.turns[8].points, 20);
+    assert_eq!(
+        trace.turns[8].container(factory_id).unwrap().resources,
+        Resources::new([1, 0, 0, 0, 0, 0, 0, 0])
+    );
+
+    // the conveyor loop keeps the remaining resources circulating
+    let last = trace.turns.last().unwrap();
+    let in_transit = last
+        .connections
+        .iter()
+        .map(|c| c.resources[ResourceType::Type0])
+        .sum::<u16>();
+    assert!(in_transit > 0);
+}
+
+#[test]
+fn place_solution_001() {
+    let input = std::fs::read_to_string("../tasks/001/task.json").unwrap();
+    let task: dto::Task = serde_json::from_str(&input).unwrap();
+    let mut sim = Sim::try_from(&task).unwrap();
+
+    let input = std::fs::read_to_string("../solutions/001/solution.json").unwrap();
+    let solution: dto::Solution = serde_json::from_str(&input).unwrap();
+    solution.place(&mut sim).unwrap();
+
+    let run = run(&sim);
+    assert_eq!(
+        run,
+        SimRun {
+            rounds: 50,
+            points: 410,
+            at_turn: 49,
+        }
+    );
+}
+
+#[test]
+fn compiled_runs_match_stepping_through_the_turns() {
+    let mut sims = Vec::new();
+    for path in [
+        "../tasks/003/solution_001.json",
+        "../tasks/003/solution_002.json",
+        "../tests/001/test.json",
+    ] {
+        let input = std::fs::read_to_string(path).unwrap();
+        let task: dto::Task = serde_json::from_str(&input).unwrap();
+        sims.push(Sim::try_from(&task).unwrap());
+    }
+
+    // two mines on one deposit, one of them leading into a dead end, cut off by the turn limit
+    // before the deposit is empty
+    let mut products = Products::default();
+    products[ProductType::Type0] = Product::new(Resources::new([4, 0, 0, 0, 0, 0, 0, 0]), 3);
+    let mut sim = Sim::new(products, Board::new(20, 10), 12, TIME);
+    let building = Building::Deposit(Deposit::new((0, 0), 4, 4, ResourceType::Type0));
+    place_building(&mut sim, building).unwrap();
+    let building = Building::Mine(Mine::new((5, 1), Rotation::Right));
+    place_building(&mut sim, building).unwrap();
+    let building = Building::Mine(Mine::new((1, 5), Rotation::Down));
+    place_building(&mut sim, building).unwrap();
+    let building = Building::Conveyor(Conveyor::new((2, 8), Rotation::Right, true));
+    place_building(&mut sim, building).unwrap();
+    let building = Building::Factory(Factory::new((8, 0), ProductType::Type0));
+    place_building(&mut sim, building).unwrap();
+    sims.push(sim);
+
+    for sim in sims.iter() {
+        let compiled = CompiledSim::new(sim);
+        let stepped = run_traced(sim).run;
+        assert_eq!(compiled.run_stepped(), stepped);
+        assert_eq!(compiled.run(), stepped);
+    }
+    // the conveyor loop of the test can only be stepped through
+    assert!(!CompiledSim::new(&sims[2]).is_analytic());
+    assert!(CompiledSim::new(&sims[3]).is_analytic());
+    assert_eq!(run(&sims[3]).points, 21);
+}
+
+#[test]
+fn analysis_explains_the_points_of_a_layout() {
+    let mut products = Products::default();
+    products[ProductType::Type0] = Product::new(Resources::new([7, 0, 0, 0, 0, 0, 0, 0]), 9);
+    let mut sim = Sim::new(products, Board::new(20, 10), TURNS, TIME);
+    let building = Building::Deposit(Deposit::new((0, 0), 4, 4, ResourceType::Type0));
+    let deposit_id = place_building(&mut sim, building).unwrap();
+    let building = Building::Mine(Mine::new((5, 1), Rotation::Right));
+    place_building(&mut sim, building).unwrap();
+    let building = Building::Factory(Factory::new((8, 0), ProductType::Type0));
+    let factory_id = place_building(&mut sim, building).unwrap();
+
+    let analysis = analyze(&sim);
+    assert_eq!(analysis.factories.len(), 1);
+    let factory = &analysis.factories[0];
+    assert_eq!(factory.id, factory_id);
+    assert_eq!(factory.rate, Resources::new([3, 0, 0, 0, 0, 0, 0, 0]));
+    assert_eq!(
+        factory.deposits,
+        vec![DepositInflow {
+            deposit_id,
+            resource_type: ResourceType::Type0,
+            paths: 1,
+            first_arrival: arrival_turn(1),
+            delivered: 80,
+        }]
+    );
+    assert_eq!(factory.expected_products, 11);
+    assert_eq!(factory.expected_points, run(&sim).points);
+    assert_eq!(delivered_resources(80, 3, arrival_turn(1), TURNS), 80);
+
+    // cut off by the turn limit before the deposit is empty
+    sim.turns = 12;
+    let factory = &analyze(&sim).factories[0];
+    assert_eq!(factory.deposits[0].delivered, 30);
+    assert_eq!(delivered_resources(80, 3, arrival_turn(1), 12), 30);
+    assert_eq!(factory.expected_points, run(&sim).points);
+
+    for path in [
+        "../tasks/003/solution_001.json",
+        "../tasks/003/solution_002.json",
+    ] {
+        let input = std::fs::read_to_string(path).unwrap();
+        let task: dto::Task = serde_json::from_str(&input).unwrap();
+        let sim = Sim::try_from(&task).unwrap();
+        let points = analyze(&sim)
+            .factories
+            .iter()
+            .map(|f| f.expected_points)
+            .sum::<u32>();
+        assert_eq!(points, run(&sim).points);
+    }
+}
+
+#[test]
+fn evaluator_matches_run_after_every_edit() {
+    let input = std::fs::read_to_string("../tasks/001/task.json").unwrap();
+    let task: dto::Task = serde_json::from_str(&input).unwrap();
+    let mut sim = Sim::try_from(&task).unwrap();
+    let input = std::fs::read_to_string("../solutions/001/solution.json").unwrap();
+    let solution: dto::Solution = serde_json::from_str(&input).unwrap();
+    solution.place(&mut sim).unwrap();
+
+    let mut evaluator = Evaluator::new();
+    assert_eq!(evaluator.run(&sim), run(&sim));
+
+    // take the solution apart and put it back together
+    let ids = sim
+        .buildings
+        .iter()
+        .filter(|(_, b)| !matches!(b, Building::Deposit(_) | Building::Obstacle(_)))
+        .map(|(id, _)| id)
+        .collect::<Vec<_>>();
+    let mut removed = Vec::new();
+    for id in ids {
+        removed.push(remove_building(&mut sim, id));
+        assert_eq!(evaluator.run(&sim), run(&sim));
+    }
+    for building in removed.into_iter().rev() {
+        place_building(&mut sim, building).unwrap();
+        assert_eq!(evaluator.run(&sim), run(&sim));
+    }
+    assert_eq!(evaluator.run(&sim).points, 410);
+}
+
+#[test]
+fn evaluator_reuses_unchanged_components() {
+    let mut products = Products::default();
+    products[ProductType::Type0] = Product::new(Resources::new([7, 0, 0, 0, 0, 0, 0, 0]), 9);
+    let mut sim = Sim::new(products, Board::new(30, 10), TURNS, TIME);
+    for x in [0, 14] {
+        let building = Building::Deposit(Deposit::new((x, 0), 4, 4, ResourceType::Type0));
+        place_building(&mut sim, building).unwrap();
+        let building = Building::Mine(Mine::new((x + 5, 1), Rotation::Right));
+        place_building(&mut sim, building).unwrap();
+        let building = Building::Factory(Factory::new((x + 8, 0), ProductType::Type0));
+        place_building(&mut sim, building).unwrap();
+    }
+
+    let mut evaluator = Evaluator::new();
+    let both = evaluator.run(&sim);
+    assert_eq!(both, run(&sim));
+    assert_eq!(both.points, 198);
+    assert_eq!(evaluator.cached(), 2);
+
+    // removing the second factory only changes the second component
+    let factory_id = sim
+        .buildings
+        .iter()
+        .filter(|(_, b)| matches!(b, Building::Factory(_)))
+        .map(|(id, _)| id)
+        .last()
+        .unwrap();
+    remove_building(&mut sim, factory_id);
+    assert_eq!(evaluator.run(&sim), run(&sim));
+    assert_eq!(evaluator.cached(), 3);
+
+    // building the second component first gives all buildings other ids, but the components
+    // stay the same
+    let mut reordered = Sim::new(sim.products.clone(), Board::new(30, 10), TURNS, TIME);
+    let buildings = sim
+        .buildings
+        .iter()
+        .map(|(_, b)| b.clone())
+        .collect::<Vec<_>>();
+    let (first, second) = buildings.split_at(3);
+    for building in second.iter().chain(first) {
+        place_building(&mut reordered, building.clone()).unwrap();
+    }
+    assert_eq!(evaluator.run(&reordered), run(&sim));
+    assert_eq!(evaluator.cached(), 3);
+}
+
+#[test]
+fn place_solution_reports_object_index() {
+    let input = std::fs::read_to_string("../tasks/001/task.json").unwrap();
+    let task: dto::Task = serde_json::from_str(&input).unwrap();
+    let mut sim = Sim::try_from(&task).unwrap();
+
+    let solution = dto::Solution::new(vec![
+        dto::SolutionObject {
+            kind: dto::ObjectKind::Factory,
+            subtype: 0,
+            x: 13,
+            y: 3,
+        },
+        dto::SolutionObject {
+            kind: dto::ObjectKind::Conveyor,
+            subtype: 0,
+            x: 15,
+            y: 5,
+        },
+    ]);
+    let err = solution.place(&mut sim).unwrap_err();
+
+    assert_eq!(
+        err,
+        dto::ObjectError {
+            index: 1,
+            error: Error::Interseciton(pos(14, 5)),
+        }
+    );
+}
+
+#[test]
+fn invalid_task_reports_object_index() {
+    let input = std::fs::read_to_string("../tasks/001/task.json").unwrap();
+    let mut task: dto::Task = serde_json::from_str(&input).unwrap();
+    task.objects.push(TaskObject {
+        kind: dto::ObjectKind::Conveyor,
+        subtype: 8,
+        x: 0,
+        y: 0,
+        width: 0,
+        height: 0,
+    });
+
+    let err = Sim::try_from(&task).unwrap_err();
+
+    assert_eq!(
+        err,
+        dto::TaskError::Object(dto::ObjectError {
+            index: task.objects.len() - 1,
+            error: Error::Io(IoError::UnknownConveyorSubtype(8)),
+        })
+    );
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "object {}: Unknown conveyor subtype '8'",
+            task.objects.len() - 1
+        )
+    );
+}
+
+#[test]
+fn place_all_reports_every_conflict() {
+    let input = std::fs::read_to_string("../tasks/001/task.json").unwrap();
+    let task: dto::Task = serde_json::from_str(&input).unwrap();
+    let mut sim = Sim::try_from(&task).unwrap();
+
+    let object = |kind, subtype, x, y| dto::SolutionObject {
+        kind,
+        subtype,
+        x,
+        y,
+    };
+    let solution = dto::Solution::new(vec![
+        object(dto::ObjectKind::Factory, 0, 13, 3),
+        // overlaps the factory
+        object(dto::ObjectKind::Conveyor, 0, 15, 5),
+        // overlaps the second obstacle
+        object(dto::ObjectKind::Mine, 0, 10, 4),
+        // input next to the first deposit
+        object(dto::ObjectKind::Conveyor, 0, 7, 3),
+        object(dto::ObjectKind::Conveyor, 2, 0, 19),
+        // valid
+        object(dto::ObjectKind::Mine, 0, 6, 5),
+    ]);
+    let conflicts = solution.place_all(&mut sim);
+
+    assert_eq!(
+        conflicts,
+        [
+            dto::Conflict {
+                index: 1,
+                error: Error::Interseciton(pos(14, 5)),
+                other: Some(Id(5)),
+                other_index: Some(0),
+            },
+            dto::Conflict {
+                index: 2,
+                error: Error::Interseciton(pos(11, 4)),
+                other: Some(Id(4)),
+                other_index: None,
+            },
+            dto::Conflict {
+                index: 3,
+                error: Error::DepositEgress(pos(6, 3)),
+                other: Some(Id(0)),
+                other_index: None,
+            },
+            dto::Conflict {
+                index: 4,
+                error: Error::OutOfBounds(pos(-1, 19)),
+                other: None,
+                other_index: None,
+            },
+        ]
+    );
+    assert_eq!(sim.buildings.iter().count(), 7);
+}
+
+#[test]
+fn render_buildings() {
+    let mut sim = Sim::new(Products::default(), Board::new(15, 6), TURNS, TIME);
+    let buildings = [
+        Building::Deposit(Deposit::new((0, 0), 2, 2, ResourceType::Type3)),
+        Building::Obstacle(Obstacle::new((0, 4), 2, 2)),
+        Building::Mine(Mine::new((3, 0), Rotation::Right)),
+        Building::Conveyor(Conveyor::new((6, 1), Rotation::Down, true)),
+        Building::Conveyor(Conveyor::new((7, 4), Rotation::Right, false)),
+        Building::Factory(Factory::new((9, 1), ProductType::Type0)),
+    ];
+    for b in buildings {
+        place_building(&mut sim, b).unwrap();
+    }
+
+    let rendered = Render::new(&sim).color(false).to_string();
+    let expected = [
+        "     0  1  2  3  4  5  6  7  8  9 10 11 12 13 14 ",
+        "  0 D#3D-3 . M#>Mo> . C+v .  .  .  .  .  .  .  . ",
+        "  1 D-3D-3M+>Mo>Mo>M->C#v .  . F#0F+0F+0F+0F+0 . ",
+        "  2  .  .  .  .  .  . Cov .  . F+0F+0F+0F+0F+0 . ",
+        "  3  .  .  .  .  .  . C-v .  . F+0F+0F+0F+0F+0 . ",
+        "  4 X# Xo  .  .  .  . c+>c#>c->F+0F+0F+0F+0F+0 . ",
+        "  5 Xo Xo  .  .  .  .  .  .  . F+0F+0F+0F+0F+0 . ",
+    ]
+    .join("\n");
+    assert_eq!(rendered, expected);
+}
+
+#[test]
+fn export_solution_001_as_svg() {
+    let input = std::fs::read_to_string("../tasks/001/task.json").unwrap();
+    let task: dto::Task = serde_json::from_str(&input).unwrap();
+    let mut sim = Sim::try_from(&task).unwrap();
+    let input = std::fs::read_to_string("../solutions/001/solution.json").unwrap();
+    let solution: dto::Solution = serde_json::from_str(&input).unwrap();
+    solution.place(&mut sim).unwrap();
+
+    let svg = Svg::new(&sim).to_string();
+    assert!(svg.starts_with("<svg "));
+    assert!(svg.ends_with("</svg>\n"));
+    let occupied = (0..sim.board.height)
+        .flat_map(|y| (0..sim.board.width).map(move |x| pos(x, y)))
+        .filter(|p| sim.board[*p].is_some())
+        .count();
+    // one for the background
+    assert_eq!(svg.matches("<rect").count(), 1 + occupied);
+    assert_eq!(svg.matches(">P0<").count(), 1);
+    assert_eq!(svg.matches(">↑<").count(), 5);
+
+    let overlay = |p: Pos| (p.y == 0).then_some(p.x as u16);
+    let svg = Svg::new(&sim).overlay(&overlay).to_string();
+    let empty_in_first_row = (0..sim.board.width)
+        .filter(|x| sim.board[pos(*x, 0)].is_none())
+        .count();
+    assert_eq!(
+        svg.matches("<rect").count(),
+        1 + occupied + empty_in_first_row
+    );
+}
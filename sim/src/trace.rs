@@ -0,0 +1,50 @@
+use crate::{Id, ProductType, Resources, SimRun};
+
+/// The recorded states of a simulation run, see [`crate::run_traced`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trace {
+    pub run: SimRun,
+    /// One entry for every turn that was simulated, `turns.len() == run.rounds`
+    pub turns: Vec<TurnTrace>,
+}
+
+/// The state at the end of a turn
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TurnTrace {
+    pub turn: u32,
+    /// Points accumulated up to and including this turn
+    pub points: u32,
+    /// Resources held by every deposit, mine, conveyor, combiner and factory, ordered by id
+    pub containers: Vec<ContainerTrace>,
+    /// Resources in transit, in the same order as `Sim::connections`
+    pub connections: Vec<ConnectionTrace>,
+    /// Products that were produced during this turn, ordered by factory id
+    pub products: Vec<ProductionTrace>,
+}
+
+impl TurnTrace {
+    pub fn container(&self, id: Id) -> Option<&ContainerTrace> {
+        self.containers.iter().find(|c| c.id == id)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContainerTrace {
+    pub id: Id,
+    pub resources: Resources,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectionTrace {
+    pub output_id: Id,
+    pub input_id: Id,
+    pub resources: Resources,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProductionTrace {
+    pub factory_id: Id,
+    pub product_type: ProductType,
+    pub count: u16,
+    pub points: u32,
+}
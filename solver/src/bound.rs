@@ -0,0 +1,333 @@
+//! Upper bounds of the score that can be reached for a task.
+//!
+//! The resources of all deposits in a region can only ever be turned into products by factories
+//! in the same region. Choosing how many of each product to make from them is an integer program
+//! over the products, which is solved by branch and bound on top of a small simplex.
+
+use sim::{Building, Pos, Products, Resources, Sim, FACTORY_SIZE, PRODUCT_TYPES, RESOURCE_TYPES};
+
+use crate::{
+    border_cells, border_distance, factory_fits, find_regions, map_deposit_distances,
+    without_connectors,
+};
+
+/// Maximum number of branch and bound nodes, after which the bound of the LP relaxation is used
+const MAX_NODES: u32 = 10_000;
+const EPSILON: f64 = 1e-9;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScoreBound {
+    /// Points of the best product mix for the resources of every region, ignoring geometry
+    pub max_points: u32,
+    /// Like `max_points`, but only counting the resources that can be mined and delivered to a
+    /// factory within the turn limit, given the distances between deposits and factories
+    pub reachable_points: u32,
+}
+
+/// Compute upper bounds of the points that can be scored on the task
+pub fn score_bound(sim: &Sim) -> ScoreBound {
+    let regions = find_regions(sim);
+    // prebuilt paths don't keep resources from reaching a factory, they can be crossed and
+    // merged into
+    let base = without_connectors(sim);
+    let deposit_distance_maps = map_deposit_distances(&base);
+
+    let mut bound = ScoreBound::default();
+    for region in regions.iter() {
+        let mut factory_positions = region
+            .cells
+            .iter()
+            .copied()
+            .filter(|&p| factory_fits(sim, p))
+            .map(|p| (p, false))
+            .collect::<Vec<_>>();
+        // prebuilt factories next to the region, of any product
+        let prebuilt = sim.buildings.iter().filter_map(|(_, b)| match b {
+            Building::Factory(f) => Some((f.pos, true)),
+            _ => None,
+        });
+        factory_positions.extend(prebuilt.filter(|&(p, _)| {
+            border_cells(p + (-1, -1), FACTORY_SIZE + 2, FACTORY_SIZE + 2)
+                .any(|c| region.cells.contains(&c))
+        }));
+        if factory_positions.is_empty() {
+            continue;
+        }
+
+        let mut available = Resources::default();
+        let mut reachable = Resources::default();
+        for id in region.deposits.iter() {
+            let Building::Deposit(deposit) = &sim.buildings[*id] else {
+                continue;
+            };
+            let resources = &mut available[deposit.resource_type];
+            *resources = resources.saturating_add(deposit.resources());
+
+            // Every building on a path moves resources by at most 4 cells, counting the step into
+            // the next building, and delays them by one turn. A deposit outputs at most 3
+            // resources per turn into every adjacent mine.
+            let map = &deposit_distance_maps[id];
+            // The cells of a prebuilt factory are occupied, the cells around it are one step
+            // further away.
+            let Some(dist) = factory_positions
+                .iter()
+                .filter_map(|&(p, prebuilt)| match prebuilt {
+                    false => border_distance(map, p, FACTORY_SIZE, FACTORY_SIZE),
+                    true => border_distance(map, p + (-1, -1), FACTORY_SIZE + 2, FACTORY_SIZE + 2)
+                        .map(|d| d + 1),
+                })
+                .min()
+            else {
+                continue;
+            };
+            let arrival = sim::arrival_turn(min_connectors(dist));
+            let (width, height) = (deposit.width as i8, deposit.height as i8);
+            let mines = free_neighbors(&base, deposit.pos, width, height);
+            let rate = sim::MINE_RATE * mines;
+            let deliverable =
+                sim::delivered_resources(deposit.resources() as u32, rate, arrival, sim.turns);
+            let resources = &mut reachable[deposit.resource_type];
+            *resources = resources.saturating_add(deliverable as u16);
+        }
+
+        bound.max_points += max_product_points(&sim.products, available);
+        bound.reachable_points += max_product_points(&sim.products, reachable);
+    }
+
+    bound
+}
+
+/// Minimum number of buildings on a path that bridges `dist` cells between a deposit and a
+/// factory
+pub(crate) fn min_connectors(dist: u16) -> u32 {
+    (dist as u32).div_ceil(4).max(1)
+}
+
+/// Number of empty cells next to a rectangular object
+pub(crate) fn free_neighbors(sim: &Sim, pos: Pos, width: i8, height: i8) -> u32 {
+    let top_bottom = (0..width).flat_map(|x| [pos + (x, -1), pos + (x, height)]);
+    let left_right = (0..height).flat_map(|y| [pos + (-1, y), pos + (width, y)]);
+    top_bottom
+        .chain(left_right)
+        .filter(|&p| matches!(sim.board.get(p), Some(None)))
+        .count() as u32
+}
+
+/// The maximum points that can be made out of `available` resources
+pub fn max_product_points(products: &Products, available: Resources) -> u32 {
+    best_product_mix(products, available).bound
+}
+
+/// How many of every product to make out of some resources
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProductMix {
+    pub counts: [u32; PRODUCT_TYPES],
+    pub points: u32,
+    /// Upper bound of the points of any mix, equal to `points` unless the search was cut short
+    pub bound: u32,
+}
+
+/// Find the product mix that makes the most points out of `available` resources
+pub fn best_product_mix(products: &Products, available: Resources) -> ProductMix {
+    // products without any resources can't be scored
+    let products = products.iter().collect::<Vec<_>>();
+    let product_types = (0..PRODUCT_TYPES)
+        .filter(|&i| products[i].points > 0 && products[i].resources.values.iter().any(|&r| r > 0))
+        .collect::<Vec<_>>();
+    if product_types.is_empty() {
+        return ProductMix::default();
+    }
+
+    let problem = Problem {
+        objective: product_types
+            .iter()
+            .map(|&i| products[i].points as f64)
+            .collect(),
+        constraints: (0..RESOURCE_TYPES)
+            .map(|r| {
+                product_types
+                    .iter()
+                    .map(|&i| products[i].resources.values[r] as f64)
+                    .collect()
+            })
+            .collect(),
+        limits: available.values.iter().map(|&r| r as f64).collect(),
+    };
+
+    let mut search = BranchAndBound {
+        problem: &problem,
+        best: 0,
+        best_x: vec![0; product_types.len()],
+        nodes: 0,
+    };
+    let lower = vec![0; product_types.len()];
+    let upper = vec![None; product_types.len()];
+    let Some(relaxed) = search.branch(&lower, &upper) else {
+        return ProductMix::default();
+    };
+
+    let mut mix = ProductMix {
+        points: search.best,
+        bound: search.best,
+        ..ProductMix::default()
+    };
+    for (&i, &count) in product_types.iter().zip(search.best_x.iter()) {
+        mix.counts[i] = count;
+    }
+    if search.nodes > MAX_NODES {
+        // the search was cut short, the LP relaxation is still an upper bound
+        mix.bound = mix.bound.max((relaxed + EPSILON).floor() as u32);
+    }
+    mix
+}
+
+/// Maximize `objective · x` subject to `constraints · x <= limits` and `x >= 0`
+struct Problem {
+    objective: Vec<f64>,
+    constraints: Vec<Vec<f64>>,
+    limits: Vec<f64>,
+}
+
+struct BranchAndBound<'a> {
+    problem: &'a Problem,
+    best: u32,
+    best_x: Vec<u32>,
+    nodes: u32,
+}
+
+impl BranchAndBound<'_> {
+    /// Search the integer solutions within the bounds and return the value of the LP relaxation
+    fn branch(&mut self, lower: &[u32], upper: &[Option<u32>]) -> Option<f64> {
+        self.nodes += 1;
+
+        let (value, x) = solve_relaxation(self.problem, lower, upper)?;
+        if self.nodes > MAX_NODES || (value + EPSILON).floor() as u32 <= self.best {
+            return Some(value);
+        }
+
+        let fractional = x
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i, v - v.floor()))
+            .filter(|(_, f)| *f > EPSILON && *f < 1.0 - EPSILON)
+            .max_by(|(_, a), (_, b)| (a - 0.5).abs().total_cmp(&(b - 0.5).abs()).reverse());
+        let Some((i, _)) = fractional else {
+            let points = (value + EPSILON).floor() as u32;
+            if points > self.best {
+                self.best = points;
+                self.best_x = x.iter().map(|v| v.round() as u32).collect();
+            }
+            return Some(value);
+        };
+
+        let floor = x[i].floor() as u32;
+        let mut down = upper.to_vec();
+        down[i] = Some(floor);
+        self.branch(lower, &down);
+        let mut up = lower.to_vec();
+        up[i] = floor + 1;
+        self.branch(&up, upper);
+
+        Some(value)
+    }
+}
+
+/// Solve the LP relaxation with `lower <= x <= upper`
+///
+/// The lower bounds are substituted, so that `x = 0` stays a feasible start for the simplex.
+fn solve_relaxation(
+    problem: &Problem,
+    lower: &[u32],
+    upper: &[Option<u32>],
+) -> Option<(f64, Vec<f64>)> {
+    let n = problem.objective.len();
+
+    let mut rows = Vec::new();
+    for (row, &limit) in problem.constraints.iter().zip(problem.limits.iter()) {
+        let used: f64 = row.iter().zip(lower).map(|(a, &l)| a * l as f64).sum();
+        if used > limit + EPSILON {
+            return None;
+        }
+        rows.push((row.clone(), (limit - used).max(0.0)));
+    }
+    for (i, u) in upper.iter().enumerate() {
+        let Some(u) = *u else { continue };
+        if u < lower[i] {
+            return None;
+        }
+        let mut row = vec![0.0; n];
+        row[i] = 1.0;
+        rows.push((row, (u - lower[i]) as f64));
+    }
+
+    let (value, mut x) = simplex(&problem.objective, &rows);
+    let offset: f64 = problem
+        .objective
+        .iter()
+        .zip(lower)
+        .map(|(c, &l)| c * l as f64)
+        .sum();
+    for (x, &l) in x.iter_mut().zip(lower) {
+        *x += l as f64;
+    }
+
+    Some((value + offset, x))
+}
+
+/// Maximize `objective · x` subject to `row · x <= limit` for all rows and `x >= 0`, with all
+/// limits non-negative. Uses Bland's rule, so it always terminates.
+fn simplex(objective: &[f64], rows: &[(Vec<f64>, f64)]) -> (f64, Vec<f64>) {
+    let n = objective.len();
+    let m = rows.len();
+    let width = n + m + 1;
+
+    // tableau with slack variables, the last row holds the negated objective
+    let mut tableau = vec![vec![0.0; width]; m + 1];
+    for (i, (row, limit)) in rows.iter().enumerate() {
+        tableau[i][..n].copy_from_slice(row);
+        tableau[i][n + i] = 1.0;
+        tableau[i][width - 1] = *limit;
+    }
+    for (j, c) in objective.iter().enumerate() {
+        tableau[m][j] = -c;
+    }
+    let mut basis = (n..n + m).collect::<Vec<_>>();
+
+    while let Some(col) = (0..width - 1).find(|&j| tableau[m][j] < -EPSILON) {
+        let mut pivot: Option<(usize, f64)> = None;
+        for (i, row) in tableau[..m].iter().enumerate() {
+            if row[col] > EPSILON {
+                let ratio = row[width - 1] / row[col];
+                match pivot {
+                    Some((_, r)) if ratio > r + EPSILON => (),
+                    Some((p, r)) if ratio > r - EPSILON && basis[p] < basis[i] => (),
+                    _ => pivot = Some((i, ratio)),
+                }
+            }
+        }
+        // unbounded, which can't happen since every product needs resources
+        let Some((row, _)) = pivot else { break };
+
+        let factor = tableau[row][col];
+        tableau[row].iter_mut().for_each(|v| *v /= factor);
+        let pivot_row = tableau[row].clone();
+        for (i, r) in tableau.iter_mut().enumerate() {
+            if i == row || r[col].abs() < EPSILON {
+                continue;
+            }
+            let factor = r[col];
+            r.iter_mut()
+                .zip(&pivot_row)
+                .for_each(|(v, p)| *v -= factor * p);
+        }
+        basis[row] = col;
+    }
+
+    let mut x = vec![0.0; n];
+    for (i, &b) in basis.iter().enumerate() {
+        if b < n {
+            x[b] = tableau[i][width - 1];
+        }
+    }
+    (tableau[m][width - 1], x)
+}
@@ -0,0 +1,38 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Handle to stop a running solver early, all clones share the same state. The solver threads
+/// check it between connection attempts, while searching paths and between improvement steps, so
+/// they stop promptly and leave the best solution found so far behind.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    /// States of the tokens this one was derived from, cancelling any of them cancels this one
+    parents: Vec<Arc<AtomicBool>>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A token that is cancelled along with this one, but can also be cancelled on its own
+    /// without affecting this one
+    pub fn child(&self) -> Self {
+        let mut parents = self.parents.clone();
+        parents.push(self.cancelled.clone());
+        Self {
+            cancelled: Arc::default(),
+            parents,
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self.parents.iter().any(|p| p.load(Ordering::Relaxed))
+    }
+}
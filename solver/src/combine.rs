@@ -1,9 +1,8 @@
 use std::sync::{mpsc, Mutex};
 
-use sim::{Building, Buildings, Sim, SimRun};
-
-const MAX_COMBINATIONS: f32 = 1000.0;
+use sim::{Building, Buildings, Evaluator, Sim, SimRun};
 
+#[allow(clippy::large_enum_variant)]
 pub enum CombineMessage {
     Some((usize, ScoredSolution)),
     Done,
@@ -42,26 +41,31 @@ impl PartialOrd for ScoredSolution {
 }
 
 pub fn combine_solutions(
+    task: &Sim,
     receiver: mpsc::Receiver<CombineMessage>,
     best_solution: &Mutex<Option<ScoredSolution>>,
+    improvements: &mpsc::Sender<ScoredSolution>,
     num_regions: usize,
+    max_combinations: u32,
 ) {
-    let num_components = (MAX_COMBINATIONS.log(num_regions as f32) as usize).max(1);
+    let num_components = ((max_combinations as f32).log(num_regions as f32) as usize).max(1);
 
     let mut regional_solutions: Vec<Vec<ScoredSolution>> = vec![Vec::new(); num_regions];
-    let mut best_local_solution = None;
+    let mut best_local_solution: Option<ScoredSolution> = None;
+    let mut evaluator = Evaluator::new();
 
     while let Ok(message) = receiver.recv() {
         let (region_idx, region_solution) = match message {
             CombineMessage::Some(s) => s,
             CombineMessage::Done => break,
         };
+        let previous_run = best_local_solution.as_ref().map(|s| s.run.clone());
 
         if num_regions > 1 {
-            let mut current_sim = region_solution.sim.clone();
-
             recursive_permutations(
-                &mut current_sim,
+                task,
+                &region_solution.sim,
+                &mut evaluator,
                 &mut best_local_solution,
                 &regional_solutions,
                 region_idx,
@@ -69,13 +73,16 @@ pub fn combine_solutions(
                 num_components,
             );
         } else {
-            let run = sim::run(&region_solution.sim);
+            let run = evaluator.run(&region_solution.sim);
             cmp_and_set(&mut best_local_solution, &region_solution.sim, run);
         }
 
         // update best solution to turn in
-        let mut lock = best_solution.lock().expect("lock not to be poisoned");
-        *lock = best_local_solution.clone();
+        if let Some(best) = &best_local_solution {
+            if previous_run.as_ref() != Some(&best.run) {
+                publish(best_solution, improvements, best);
+            }
+        }
 
         let current_region_solutions = &mut regional_solutions[region_idx];
         #[allow(irrefutable_let_patterns)]
@@ -85,8 +92,11 @@ pub fn combine_solutions(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn recursive_permutations(
+    task: &Sim,
     sim: &Sim,
+    evaluator: &mut Evaluator,
     best_solution: &mut Option<ScoredSolution>,
     region_solutions: &[Vec<ScoredSolution>],
     skip_idx: usize,
@@ -98,22 +108,38 @@ fn recursive_permutations(
     }
 
     if region_idx >= region_solutions.len() {
-        let run = sim::run(sim);
+        let run = evaluator.run(sim);
         cmp_and_set(best_solution, sim, run);
         return;
     }
 
     let solutions = &region_solutions[region_idx];
+    // regions without a solution yet don't add any buildings
+    if solutions.is_empty() {
+        recursive_permutations(
+            task,
+            sim,
+            evaluator,
+            best_solution,
+            region_solutions,
+            skip_idx,
+            region_idx + 1,
+            num_components,
+        );
+        return;
+    }
 
     let mut current_sim = sim.clone();
     for s in solutions.iter().rev().take(num_components) {
         current_sim.clone_from(sim);
-        let res = add_solution_buildings(&mut current_sim, &s.sim.buildings);
+        let res = add_solution_buildings(task, &mut current_sim, &s.sim.buildings);
         if res.is_err() {
             continue;
         }
         recursive_permutations(
+            task,
             &current_sim,
+            evaluator,
             best_solution,
             region_solutions,
             skip_idx,
@@ -123,8 +149,13 @@ fn recursive_permutations(
     }
 }
 
-fn add_solution_buildings(sim: &mut Sim, buildings: &Buildings) -> sim::Result<()> {
-    for (_, b) in buildings.iter() {
+/// Place the buildings of a regional solution, except the ones of the task that every solution
+/// shares
+fn add_solution_buildings(task: &Sim, sim: &mut Sim, buildings: &Buildings) -> sim::Result<()> {
+    for (id, b) in buildings.iter() {
+        if task.buildings.get(id).is_some() {
+            continue;
+        }
         match b {
             Building::Deposit(_) | Building::Obstacle(_) => (),
             Building::Mine(_)
@@ -139,6 +170,20 @@ fn add_solution_buildings(sim: &mut Sim, buildings: &Buildings) -> sim::Result<(
     Ok(())
 }
 
+/// Turn in a new best solution and pass it on to whoever listens for improvements
+pub(crate) fn publish(
+    best_solution: &Mutex<Option<ScoredSolution>>,
+    improvements: &mpsc::Sender<ScoredSolution>,
+    solution: &ScoredSolution,
+) {
+    let mut lock = best_solution.lock().expect("lock not to be poisoned");
+    *lock = Some(solution.clone());
+    drop(lock);
+
+    // nobody listens anymore once the time is up
+    let _ = improvements.send(solution.clone());
+}
+
 fn cmp_and_set(best_solution: &mut Option<ScoredSolution>, sim: &Sim, run: SimRun) {
     match best_solution {
         None => *best_solution = Some(ScoredSolution::new(sim.clone(), run)),
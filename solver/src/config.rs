@@ -0,0 +1,135 @@
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+/// Configuration of a solver run, missing fields of a config file are filled in with the defaults
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SolverConfig {
+    /// Seed of the random number generator used to break ties between equally ranked factory
+    /// positions
+    pub seed: u64,
+    pub budget: Budget,
+    /// Seconds before the time limit of the task at which the search is stopped, to leave time
+    /// for submitting the solution
+    pub safety_margin: f32,
+    /// Maximum number of regional solution combinations evaluated per new regional solution
+    pub max_combinations: u32,
+    /// Number of connected deposits that don't improve the score after which connecting further
+    /// deposits to a factory is stopped
+    pub max_non_improvements: u32,
+    /// Maximum number of path buildings the search for a path from a deposit to a factory expands,
+    /// after which the deposit counts as unreachable
+    pub max_route_nodes: u32,
+    /// What the paths from deposits to factories are optimized for
+    pub route_objective: RouteObjective,
+    /// Maximum number of factories built in a single region
+    pub max_factories: u32,
+    /// Number of the best ranked free factory positions per product that are tried when adding
+    /// another factory to a region
+    pub factory_candidates: u32,
+    /// Number of mutations tried in the improvement phase, if the budget is counted in iterations.
+    /// With a time budget the improvement phase runs until the time is up.
+    pub improvement_steps: u32,
+    /// Temperature at the start of the improvement phase, in points. A mutation that loses `t`
+    /// points is accepted with a probability of `e^(-t / temperature)`.
+    pub initial_temperature: f32,
+    /// Number of worker threads searching factory positions in parallel, 0 starts one per CPU
+    /// core. The results don't depend on it, only how many positions are tried in time.
+    pub threads: u32,
+}
+
+impl Default for SolverConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            budget: Budget::Time,
+            safety_margin: 0.1,
+            max_combinations: 1000,
+            max_non_improvements: 5,
+            max_route_nodes: 20_000,
+            route_objective: RouteObjective::Arrival,
+            max_factories: 4,
+            factory_candidates: 8,
+            improvement_steps: 200,
+            initial_temperature: 10.0,
+            threads: 0,
+        }
+    }
+}
+
+impl SolverConfig {
+    pub fn validate(&self) -> crate::Result<()> {
+        if self.safety_margin.is_nan() || self.safety_margin < 0.0 {
+            return Err(crate::Error::InvalidConfig(
+                "safety_margin must not be negative",
+            ));
+        }
+        if self.initial_temperature.is_nan() || self.initial_temperature < 0.0 {
+            return Err(crate::Error::InvalidConfig(
+                "initial_temperature must not be negative",
+            ));
+        }
+        if self.max_combinations == 0 {
+            return Err(crate::Error::InvalidConfig(
+                "max_combinations must be at least 1",
+            ));
+        }
+        if self.max_non_improvements == 0 {
+            return Err(crate::Error::InvalidConfig(
+                "max_non_improvements must be at least 1",
+            ));
+        }
+        if self.max_route_nodes == 0 {
+            return Err(crate::Error::InvalidConfig(
+                "max_route_nodes must be at least 1",
+            ));
+        }
+        if self.max_factories == 0 {
+            return Err(crate::Error::InvalidConfig(
+                "max_factories must be at least 1",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Number of worker threads to start
+    pub(crate) fn worker_threads(&self) -> usize {
+        match self.threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n as usize,
+        }
+    }
+
+    /// Whether the budget is exhausted after `iterations` connection attempts
+    pub(crate) fn is_exhausted(&self, iterations: u32, start: Instant, time: f32) -> bool {
+        match self.budget {
+            Budget::Time => start.elapsed().as_secs_f32() > time - self.safety_margin,
+            Budget::Iterations(max) => iterations >= max,
+        }
+    }
+}
+
+/// How much work the solver is allowed to do
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Budget {
+    /// Search until the time limit of the task is almost reached
+    Time,
+    /// Search for a fixed number of factory connection attempts, independent of the wall clock.
+    /// Together with a fixed seed this makes solver runs reproducible.
+    Iterations(u32),
+}
+
+/// What the paths from deposits to factories are optimized for
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteObjective {
+    /// The fewest buildings on the way to the factory, including those of paths that are merged
+    /// into, so that resources arrive as early as possible. Best for tasks with few turns.
+    #[default]
+    Arrival,
+    /// The fewest covered cells, so that more room is left for other paths. Best for crowded
+    /// boards.
+    Footprint,
+}
@@ -1,155 +1,222 @@
-use std::cmp::Ordering;
+//! Best first search for paths from deposits to factories.
+//!
+//! A path starts with a mine next to the deposit, followed by conveyors and combiners. The search
+//! state is the egress of the last building of a path, together with the kind and rotation of that
+//! building, since its cells decide which buildings fit next. Every building delays resources by
+//! one turn and moves them by at most 4 cells, so the distance of the egress to the factory divided
+//! by 4 is an estimate of the buildings still needed. With it as heuristic the A* search looks
+//! for a path with few buildings, so that resources arrive early. Alternatively the search
+//! minimizes the cells covered by a path, which leaves more room for later paths.
+//!
+//! The state leaves out the cells the path itself covers, only the cheapest path to a state is
+//! expanded. If its buildings block the way a costlier path to the same state would have left
+//! free, the search settles for a longer path or none at all, so the result is short but not
+//! guaranteed to be the shortest.
+//!
+//! Conveyors may cross other conveyors perpendicularly through their inert cells, so paths to the
+//! same or other factories can intersect instead of going around each other. A crossed cell is
+//! shared and only counts for the conveyor that was placed first. A big conveyor crossing two
+//! others covers just 2 new cells while moving the egress by 4, so half the distance estimates
+//! the cells still needed.
+//!
+//! Distances are mapped on the board without any mines, conveyors and combiners, since paths can
+//! cross conveyors and merge into other paths leading to the factory. The estimates only hold for
+//! new paths though. Merging into another path near its ingress can take far fewer new cells than
+//! the distance suggests, so the search may miss cheaper paths that merge.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
 
 use sim::{
-    Building, CellKind, Combiner, Conveyor, Factory, Id, Mine, Pos, Rotation, Sim, FACTORY_SIZE,
+    pos, Building, Combiner, Conveyor, Evaluator, Factory, Id, Mine, Pos, Rotation, Sim,
+    FACTORY_SIZE,
 };
-use smallvec::SmallVec;
 
 use crate::combine::ScoredSolution;
-use crate::{map_distances, DistanceMap, FactoryStats, ProductStats};
+use crate::{
+    existing_factory, map_distances, without_connectors, CancelToken, DistanceMap, FactoryStats,
+    ProductStats, RouteObjective, SolverConfig,
+};
 
 #[cfg(test)]
 mod test;
 
+/// Placements of a mine relative to its ingress next to the deposit, as rotation, offset of the
+/// mine and offset of its egress
+#[rustfmt::skip]
+const MINE_PLACEMENTS: [(Rotation, Pos, Pos); 4] = [
+    (Rotation::Right, pos(1, -1),  pos(3, 0)),
+    (Rotation::Down,  pos(0, 1),   pos(0, 3)),
+    (Rotation::Left,  pos(-2, 0),  pos(-3, 0)),
+    (Rotation::Up,    pos(-1, -2), pos(0, -3)),
+];
+
+/// Placements of conveyors and combiners relative to their ingress, as kind, rotation, offset of
+/// the building and offset of its egress
+#[rustfmt::skip]
+const CONNECTOR_PLACEMENTS: [(ConnectorKind, Rotation, Pos, Pos); 20] = [
+    (ConnectorKind::SmallConveyor, Rotation::Right, pos(1, 0),   pos(2, 0)),
+    (ConnectorKind::SmallConveyor, Rotation::Down,  pos(0, 1),   pos(0, 2)),
+    (ConnectorKind::SmallConveyor, Rotation::Left,  pos(-1, 0),  pos(-2, 0)),
+    (ConnectorKind::SmallConveyor, Rotation::Up,    pos(0, -1),  pos(0, -2)),
+
+    (ConnectorKind::BigConveyor,   Rotation::Right, pos(1, 0),   pos(3, 0)),
+    (ConnectorKind::BigConveyor,   Rotation::Down,  pos(0, 1),   pos(0, 3)),
+    (ConnectorKind::BigConveyor,   Rotation::Left,  pos(-2, 0),  pos(-3, 0)),
+    (ConnectorKind::BigConveyor,   Rotation::Up,    pos(0, -2),  pos(0, -3)),
+
+    (ConnectorKind::Combiner,      Rotation::Right, pos(1, 1),   pos(2, 1)),
+    (ConnectorKind::Combiner,      Rotation::Right, pos(1, 0),   pos(2, 0)),
+    (ConnectorKind::Combiner,      Rotation::Right, pos(1, -1),  pos(2, -1)),
+
+    (ConnectorKind::Combiner,      Rotation::Down,  pos(1, 1),   pos(1, 2)),
+    (ConnectorKind::Combiner,      Rotation::Down,  pos(0, 1),   pos(0, 2)),
+    (ConnectorKind::Combiner,      Rotation::Down,  pos(-1, 1),  pos(-1, 2)),
+
+    (ConnectorKind::Combiner,      Rotation::Left,  pos(-1, 1),  pos(-2, 1)),
+    (ConnectorKind::Combiner,      Rotation::Left,  pos(-1, 0),  pos(-2, 0)),
+    (ConnectorKind::Combiner,      Rotation::Left,  pos(-1, -1), pos(-2, -1)),
+
+    (ConnectorKind::Combiner,      Rotation::Up,    pos(1, -1),  pos(1, -2)),
+    (ConnectorKind::Combiner,      Rotation::Up,    pos(0, -1),  pos(0, -2)),
+    (ConnectorKind::Combiner,      Rotation::Up,    pos(-1, -1), pos(-1, -2)),
+];
+
+const OFFSETS: [Pos; 4] = [pos(-1, 0), pos(1, 0), pos(0, 1), pos(0, -1)];
+
 struct Context<'a> {
     sim: &'a mut Sim,
     tree: &'a mut ConnectionTree,
     distance_map: DistanceMap,
     factory_id: Id,
+    objective: RouteObjective,
+    /// Maximum number of expanded nodes before the search gives up
+    max_nodes: u32,
+    cancel: &'a CancelToken,
 }
 
+/// The nodes of a path search, kept between searches to reuse the allocations. Every search starts
+/// by clearing it, nothing found on another board or for another deposit carries over.
 pub struct ConnectionTree {
     nodes: Vec<ConnectionTreeNode>,
-}
-
-impl std::ops::Index<NodeId> for ConnectionTree {
-    type Output = ConnectionTreeNode;
-
-    fn index(&self, index: NodeId) -> &Self::Output {
-        &self.nodes[index.0 as usize]
-    }
-}
-
-impl std::ops::IndexMut<NodeId> for ConnectionTree {
-    fn index_mut(&mut self, index: NodeId) -> &mut Self::Output {
-        &mut self.nodes[index.0 as usize]
-    }
+    open: BinaryHeap<(Priority, Reverse<u32>)>,
+    /// Lowest cost of any path leading to a state, only valid for the search in progress
+    visited: HashMap<SearchState, (u16, u16)>,
+    /// Nodes of the path that is currently placed on the board, with the ids of their buildings
+    placed: Vec<(u32, Id)>,
 }
 
 impl ConnectionTree {
     pub fn new() -> Self {
-        Self { nodes: Vec::new() }
+        Self {
+            nodes: Vec::new(),
+            open: BinaryHeap::new(),
+            visited: HashMap::new(),
+            placed: Vec::new(),
+        }
     }
 
     pub fn clear(&mut self) {
         self.nodes.clear();
-    }
-
-    /// reserves size slots, and returns the starting index
-    pub fn alloc(&mut self, size: u16) -> ChildrenId {
-        let len = self.nodes.len();
-        self.nodes
-            .resize_with(len + size as usize, ConnectionTreeNode::uninitialized);
-        ChildrenId(len as u32)
+        self.open.clear();
+        self.visited.clear();
+        self.placed.clear();
     }
 }
 
-fn increment_id(children_id: ChildrenId, len: &mut u16) -> NodeId {
-    let id = children_id.0 + *len as u32;
-    *len += 1;
-    NodeId(id)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum ConnectorKind {
+    Mine,
+    SmallConveyor,
+    BigConveyor,
+    Combiner,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub struct ChildrenId(u32);
+impl ConnectorKind {
+    fn building(self, pos: Pos, rotation: Rotation) -> Building {
+        match self {
+            ConnectorKind::Mine => Building::Mine(Mine::new(pos, rotation)),
+            ConnectorKind::SmallConveyor => Building::Conveyor(Conveyor::new(pos, rotation, false)),
+            ConnectorKind::BigConveyor => Building::Conveyor(Conveyor::new(pos, rotation, true)),
+            ConnectorKind::Combiner => Building::Combiner(Combiner::new(pos, rotation)),
+        }
+    }
+}
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-struct NodeId(u32);
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct SearchState {
+    egress: Pos,
+    rotation: Rotation,
+    kind: ConnectorKind,
+}
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 struct ConnectionTreeNode {
-    building: ConnectionBuilding,
-    start_pos: Pos,
-    end_pos: Pos,
-    state: State,
+    building: Building,
+    state: SearchState,
+    parent: Option<u32>,
+    /// Metrics of the path up to and including this building
+    stats: PathStats,
+    /// Whether the building is connected to the factory, directly or via an existing path
+    connected: bool,
 }
 
-impl ConnectionTreeNode {
-    fn new(building: ConnectionBuilding, start_pos: Pos, end_pos: Pos, state: State) -> Self {
-        Self {
-            building,
-            start_pos,
-            end_pos,
-            state,
-        }
-    }
-
-    fn uninitialized() -> Self {
-        Self {
-            building: ConnectionBuilding::Mine(Mine::new((i8::MIN, i8::MIN), Rotation::Right)),
-            start_pos: Pos::new(i8::MIN, i8::MIN),
-            end_pos: Pos::new(i8::MIN, i8::MIN),
-            state: State::Stopped,
-        }
-    }
+/// Metrics of a path from a deposit to a factory
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PathStats {
+    /// Number of buildings resources pass through, including those of an existing path it merges
+    /// into. Resources take one turn more than this to reach the factory.
+    pub buildings: u16,
+    /// Number of cells covered by the new buildings
+    pub cells: u16,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-enum ConnectionBuilding {
-    Mine(Mine),
-    Conveyor(Conveyor),
-    Combiner(Combiner),
+/// A path of a layout from a mine into a factory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MeasuredPath {
+    pub mine_id: Id,
+    pub factory_id: Id,
+    pub stats: PathStats,
 }
 
-impl ConnectionBuilding {
-    fn to_building(&self) -> Building {
-        match self {
-            ConnectionBuilding::Mine(m) => Building::Mine(*m),
-            ConnectionBuilding::Conveyor(c) => Building::Conveyor(*c),
-            ConnectionBuilding::Combiner(c) => Building::Combiner(*c),
+impl PathStats {
+    /// Cost of the path under the objective, compared lexicographically. The cells don't matter
+    /// for the arrival, but ties in cells are broken by the earlier arrival.
+    fn cost(&self, objective: RouteObjective) -> (u16, u16) {
+        match objective {
+            RouteObjective::Arrival => (self.buildings, 0),
+            RouteObjective::Footprint => (self.cells, self.buildings),
         }
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-enum State {
-    /// Search depth was exceeded, this path might be continued further
-    Stopped,
-    /// Connected to the factory
-    Connected,
-    /// Connected to the factory via an already existent path
-    Merged,
-    /// A list of children
-    Children {
-        /// Start index into the connection tree nodes
-        start: ChildrenId,
-        /// Length of the children array
-        len: u16,
-    },
-}
-
-#[derive(Clone, Debug, PartialEq, Eq)]
-struct PathStats {
-    dist: u16,
-    /// flipped depth -> lower is deeper
-    depth: u8,
+/// Priority of a node in the search, better nodes compare greater
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Priority {
+    /// Lower bound of the cost of a complete path through the node
+    estimate: (u16, u16),
+    /// Lower bound of the cost still to come, under the first metric of the objective
+    remaining: u16,
 }
 
-impl Ord for PathStats {
+impl Ord for Priority {
     fn cmp(&self, other: &Self) -> Ordering {
-        Ord::cmp(&other.dist, &self.dist).then(Ord::cmp(&self.depth, &other.depth))
+        Ord::cmp(&other.estimate, &self.estimate).then(Ord::cmp(&other.remaining, &self.remaining))
     }
 }
 
-impl PartialOrd for PathStats {
+impl PartialOrd for Priority {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl PathStats {
-    fn new(dist: u16, depth: u8) -> Self {
-        Self { dist, depth }
+impl Priority {
+    fn new(estimate: (u16, u16), remaining: u16) -> Self {
+        Self {
+            estimate,
+            remaining,
+        }
     }
 }
 
@@ -158,20 +225,30 @@ pub(crate) fn connect_deposits_and_factory(
     tree: &mut ConnectionTree,
     product_stats: &ProductStats,
     factory_stats: &FactoryStats,
-    search_depth: u8,
+    config: &SolverConfig,
+    evaluator: &mut Evaluator,
+    cancel: &CancelToken,
 ) -> crate::Result<ScoredSolution> {
     let mut runs: Vec<ScoredSolution> = Vec::new();
+    let distance_map = map_connector_distances(sim, factory_stats.pos);
     for offset in 0..factory_stats.deposits_in_reach.len() {
         let mut current_sim = sim.clone();
         let product_type = product_stats.product_type;
-        let factory = Building::Factory(Factory::new(factory_stats.pos, product_type));
-        let factory_id = sim::place_building(&mut current_sim, factory)?;
-        let distance_map = map_distances(sim, factory_stats.pos, FACTORY_SIZE, FACTORY_SIZE);
+        let factory_id = match existing_factory(&current_sim, factory_stats.pos, product_type) {
+            Some(id) => id,
+            None => {
+                let factory = Building::Factory(Factory::new(factory_stats.pos, product_type));
+                sim::place_building(&mut current_sim, factory)?
+            }
+        };
         let mut ctx = Context {
             sim: &mut current_sim,
-            distance_map,
+            distance_map: distance_map.clone(),
             tree,
             factory_id,
+            objective: config.route_objective,
+            max_nodes: config.max_route_nodes,
+            cancel,
         };
 
         let mut non_improvements = 0;
@@ -183,105 +260,20 @@ pub(crate) fn connect_deposits_and_factory(
             .skip(offset)
             .enumerate()
         {
-            ctx.tree.clear();
+            if cancel.is_cancelled() {
+                break;
+            }
             if i % factory_stats.deposits_in_reach.len() == 0 {
                 errors = 0;
             }
 
             let deposit_stats = &product_stats.deposit_stats[d.idx];
-            let Building::Deposit(deposit) = &ctx.sim.buildings[deposit_stats.id] else { unreachable!("This should be a deposit") };
-            let deposit_pos = deposit.pos;
-            let deposit_width = deposit.width as i8;
-            let deposit_height = deposit.height as i8;
-
-            const MINE_CORNER_POSITIONS: u16 = 4;
-            const MINE_CORNER_CONFIGURATIONS: u16 = 3;
-            const MINE_EDGE_CONFIGURATIONS: u16 = 2;
-            let mine_edge_positions =
-                2 * deposit.width.saturating_sub(1) + 2 * deposit.height.saturating_sub(1);
-            let max_children_len = MINE_CORNER_POSITIONS * MINE_CORNER_CONFIGURATIONS
-                + mine_edge_positions as u16 * MINE_EDGE_CONFIGURATIONS;
-            let children_id = ctx.tree.alloc(max_children_len);
-            let mut children_len = 0;
-
-            let mut best = None;
-            // place a mine somewhere around the deposit
-            for x in 0..deposit_width {
-                let pos = deposit_pos + (x, -1);
-                if let Some(Some(_dist)) = ctx.distance_map.get(pos) {
-                    #[rustfmt::skip]
-                    let stats = place_mines(&mut ctx, pos, children_id, &mut children_len, search_depth);
-                    cmp_and_set(&mut best, stats);
-                }
-            }
-            for y in 0..deposit_height {
-                let pos = deposit_pos + (-1, y);
-                if let Some(Some(_dist)) = ctx.distance_map.get(pos) {
-                    #[rustfmt::skip]
-                    let stats = place_mines(&mut ctx, pos, children_id, &mut children_len, search_depth);
-                    cmp_and_set(&mut best, stats);
-                }
-                let pos = deposit_pos + (deposit_width, y);
-                if let Some(Some(_dist)) = ctx.distance_map.get(pos) {
-                    #[rustfmt::skip]
-                    let stats = place_mines(&mut ctx, pos, children_id, &mut children_len, search_depth);
-                    cmp_and_set(&mut best, stats);
-                }
-            }
-            for x in 0..deposit_width {
-                let pos = deposit_pos + (x, deposit_height);
-                if let Some(Some(_dist)) = ctx.distance_map.get(pos) {
-                    #[rustfmt::skip]
-                    let stats = place_mines(&mut ctx, pos, children_id, &mut children_len, search_depth);
-                    cmp_and_set(&mut best, stats);
-                }
-            }
-
-            let mut path = Vec::new();
-            let res = loop {
-                let Some((node_id, _stats)) = best else {
-                    break Err(crate::Error::NoPath(
-                        deposit_stats.id,
-                        deposit_pos,
-                        factory_stats.pos,
-                    ));
-                };
-                path.push(node_id);
-
-                let node = &ctx.tree[node_id];
-
-                let connector_id = sim::place_building(ctx.sim, node.building.to_building())
-                    .expect("connector to be valid");
-
-                match node.state {
-                    State::Connected => {
-                        break Ok(ctx.sim.clone());
-                    }
-                    State::Merged => {
-                        break Ok(ctx.sim.clone());
-                    }
-                    State::Stopped => {
-                        let end_pos = node.end_pos;
-                        let end_dist = ctx.distance_map[node.end_pos].expect("should be valid");
-
-                        #[rustfmt::skip]
-                        let (state, stats) = place_children_connectors(&mut ctx, connector_id, node_id, end_pos, end_dist, search_depth);
-
-                        ctx.tree[node_id].state = state;
-                        best = stats;
-                    }
-                    State::Children { start, len } => {
-                        #[rustfmt::skip]
-                        let stats = continue_subtree(&mut ctx, start, len, search_depth);
-
-                        best = stats;
-                    }
-                }
-            };
+            let res = connect_deposit(&mut ctx, deposit_stats.id, factory_stats.pos)
+                .map(|_| ctx.sim.clone());
 
             match res {
                 Ok(sim) => {
-                    let new = sim::run(&sim);
+                    let new = evaluator.run(&sim);
                     if let Some(last) = runs.last() {
                         // maybe don't break immediately
                         if new <= last.run {
@@ -289,7 +281,7 @@ pub(crate) fn connect_deposits_and_factory(
                         } else {
                             non_improvements = 0;
                         }
-                        if non_improvements == 5 {
+                        if non_improvements == config.max_non_improvements {
                             break;
                         }
                     }
@@ -314,456 +306,279 @@ pub(crate) fn connect_deposits_and_factory(
         .ok_or(crate::Error::NoSolution)
 }
 
-fn continue_subtree(
-    ctx: &mut Context,
-    children_id: ChildrenId,
-    len: u16,
-    search_depth: u8,
-) -> Option<(NodeId, PathStats)> {
-    let mut best = None;
-
-    for i in 0..len {
-        let node_id = NodeId(children_id.0 + i as u32);
-        let node = &mut ctx.tree[node_id];
-        match node.state {
-            State::Connected => {
-                // TODO: consider somehow storing a list of equally good paths.
-                return Some((node_id, PathStats::new(0, search_depth)));
-            }
-            State::Merged => {
-                let building_id = sim::place_building(ctx.sim, node.building.to_building()).ok()?;
-
-                let node_end_pos = node.end_pos;
-                let (_, stats) =
-                    find_connection_around(ctx, node_id, building_id, node_end_pos, search_depth)
-                        .expect("this path to be merged");
-                cmp_and_set(&mut best, stats);
-
-                sim::remove_building(ctx.sim, building_id);
-            }
-            State::Stopped => {
-                let end_pos = node.end_pos;
-                let end_dist = ctx.distance_map[node.end_pos].expect("should be valid");
-
-                let building_id = sim::place_building(ctx.sim, node.building.to_building()).ok()?;
-
-                #[rustfmt::skip]
-                let (state, stats) = place_children_connectors(ctx, building_id, node_id, end_pos, end_dist, search_depth - 1);
-                cmp_and_set(&mut best, stats.map(|(_, s)| (node_id, s)));
-
-                sim::remove_building(ctx.sim, building_id);
+/// Connect a deposit to a factory that is already placed, used to reroute paths of a solution
+pub(crate) fn reconnect_deposit(
+    sim: &mut Sim,
+    tree: &mut ConnectionTree,
+    deposit_id: Id,
+    factory_id: Id,
+    config: &SolverConfig,
+    cancel: &CancelToken,
+) -> crate::Result<PathStats> {
+    let Building::Factory(factory) = &sim.buildings[factory_id] else {
+        unreachable!("This should be a factory")
+    };
+    let factory_pos = factory.pos;
+    let distance_map = map_connector_distances(sim, factory_pos);
+    let mut ctx = Context {
+        sim,
+        tree,
+        distance_map,
+        factory_id,
+        objective: config.route_objective,
+        max_nodes: config.max_route_nodes,
+        cancel,
+    };
+    connect_deposit(&mut ctx, deposit_id, factory_pos)
+}
 
-                ctx.tree[node_id].state = state;
+/// Measure every path of the layout that leads from a mine into a factory. Buildings shared by
+/// merging paths count for each of them, like the search counts them, and so do their cells,
+/// unlike the search. Crossed cells only count for the conveyor that covers them.
+pub fn measure_paths(sim: &Sim) -> Vec<MeasuredPath> {
+    sim.buildings
+        .iter()
+        .filter(|(_, b)| matches!(b, Building::Mine(_)))
+        .filter_map(|(mine_id, _)| {
+            let path = path_from(sim, mine_id);
+            let (&factory_id, connectors) = path.split_last()?;
+            if !matches!(sim.buildings[factory_id], Building::Factory(_)) {
+                return None;
             }
-            State::Children { start, len } => {
-                let building_id = sim::place_building(ctx.sim, node.building.to_building()).ok()?;
-
-                #[rustfmt::skip]
-                let stats = continue_subtree(ctx, start, len, search_depth - 1);
-                cmp_and_set(&mut best, stats.map(|(_, s)| (node_id, s)));
+            let cells = connectors
+                .iter()
+                .map(|&id| sim::covered_cells(sim, id))
+                .sum::<u32>();
+            let stats = PathStats {
+                buildings: connectors.len() as u16,
+                cells: cells as u16,
+            };
+            Some(MeasuredPath {
+                mine_id,
+                factory_id,
+                stats,
+            })
+        })
+        .collect()
+}
 
-                sim::remove_building(ctx.sim, building_id);
-            }
+/// The buildings resources pass through, starting at `id` and ending at a factory or the last
+/// building of the path
+pub(crate) fn path_from(sim: &Sim, id: Id) -> Vec<Id> {
+    let mut path = vec![id];
+    let mut current = id;
+    while let Some(next) = sim
+        .connections
+        .iter()
+        .find(|c| c.output_id == current)
+        .map(|c| c.input_id)
+    {
+        if path.contains(&next) {
+            break;
+        }
+        path.push(next);
+        if matches!(sim.buildings[next], Building::Factory(_)) {
+            break;
         }
+        current = next;
     }
-
-    best
+    path
 }
 
-#[rustfmt::skip]
-#[inline(always)]
-fn place_mines(
-    ctx: &mut Context,
-    start_pos: Pos,
-    children_id: ChildrenId,
-    len: &mut u16,
-    search_depth: u8,
-) -> Option<(NodeId, PathStats)> {
-    let mut best = None;
-
-    let stats = place_mine(ctx, start_pos, children_id, len, search_depth, Rotation::Right,    (1,  -1), (3,  0));
-    cmp_and_set(&mut best, stats);
-    let stats = place_mine(ctx, start_pos, children_id, len, search_depth, Rotation::Down, (0,   1), (0,  3));
-    cmp_and_set(&mut best, stats);
-    let stats = place_mine(ctx, start_pos, children_id, len, search_depth, Rotation::Left,  (-2,  0), (-3, 0));
-    cmp_and_set(&mut best, stats);
-    let stats = place_mine(ctx, start_pos, children_id, len, search_depth, Rotation::Up,  (-1, -2), (0, -3));
-    cmp_and_set(&mut best, stats);
-
-    best
+/// Map the distances to a factory on the board without any mines, conveyors and combiners
+fn map_connector_distances(sim: &Sim, factory_pos: Pos) -> DistanceMap {
+    let base = without_connectors(sim);
+    map_distances(&base, factory_pos, FACTORY_SIZE, FACTORY_SIZE)
 }
 
-fn place_mine(
+/// Connect the deposit to the factory of the context by the best path under the objective of the
+/// context, and return its metrics. The board is left unchanged if no path is found.
+fn connect_deposit(
     ctx: &mut Context,
-    start_pos: Pos,
-    children_id: ChildrenId,
-    len: &mut u16,
-    search_depth: u8,
-    rotation: Rotation,
-    pos_offset: impl Into<Pos>,
-    end_offset: impl Into<Pos>,
-) -> Option<(NodeId, PathStats)> {
-    let end_pos = start_pos + end_offset;
-    let end_dist = ctx.distance_map.get(end_pos)??;
-    let mine = Mine::new(start_pos + pos_offset, rotation);
-    let building_id = sim::place_building(ctx.sim, Building::Mine(mine)).ok()?;
-
-    let node_id = increment_id(children_id, len);
-
-    #[rustfmt::skip]
-    let (state, stats) = place_children_connectors(ctx, building_id, node_id, end_pos, end_dist, search_depth - 1);
+    deposit_id: Id,
+    factory_pos: Pos,
+) -> crate::Result<PathStats> {
+    let Building::Deposit(deposit) = &ctx.sim.buildings[deposit_id] else {
+        unreachable!("This should be a deposit")
+    };
+    let deposit_pos = deposit.pos;
+    let width = deposit.width as i8;
+    let height = deposit.height as i8;
+
+    ctx.tree.clear();
+
+    // place a mine somewhere around the deposit
+    let top_bottom = (0..width).flat_map(|x| [deposit_pos + (x, -1), deposit_pos + (x, height)]);
+    let left_right = (0..height).flat_map(|y| [deposit_pos + (-1, y), deposit_pos + (width, y)]);
+    for ingress in top_bottom.chain(left_right) {
+        if !matches!(ctx.distance_map.get(ingress), Some(Some(_))) {
+            continue;
+        }
+        for (rotation, pos_offset, end_offset) in MINE_PLACEMENTS {
+            let building = ConnectorKind::Mine.building(ingress + pos_offset, rotation);
+            let state = SearchState {
+                egress: ingress + end_offset,
+                rotation,
+                kind: ConnectorKind::Mine,
+            };
+            push_node(ctx, None, building, state);
+        }
+    }
 
-    sim::remove_building(ctx.sim, building_id);
+    let mut expanded = 0;
+    while let Some((_, Reverse(node_idx))) = ctx.tree.open.pop() {
+        let node = &ctx.tree.nodes[node_idx as usize];
+        if ctx.tree.visited.get(&node.state) != Some(&node.stats.cost(ctx.objective)) {
+            // a shorter path to the same state was found after this one was queued
+            continue;
+        }
 
-    let building = ConnectionBuilding::Mine(mine);
-    let node = ConnectionTreeNode::new(building, start_pos, end_pos, state);
-    ctx.tree[node_id] = node;
+        place_path(ctx, node_idx);
+        let node = &ctx.tree.nodes[node_idx as usize];
+        if node.connected {
+            return Ok(node.stats);
+        }
 
-    stats.map(|(_, s)| (node_id, s))
-}
+        expanded += 1;
+        if expanded > ctx.max_nodes || ctx.cancel.is_cancelled() {
+            break;
+        }
 
-fn place_children_connectors(
-    ctx: &mut Context,
-    connector_id: Id,
-    parent_id: NodeId,
-    start_pos: Pos,
-    start_dist: u16,
-    search_depth: u8,
-) -> (State, Option<(NodeId, PathStats)>) {
-    if start_dist == 0 {
-        return (
-            State::Connected,
-            Some((parent_id, PathStats::new(0, search_depth))),
-        );
+        let egress = ctx.tree.nodes[node_idx as usize].state.egress;
+        for offset in OFFSETS {
+            let ingress = egress + offset;
+            for (kind, rotation, pos_offset, end_offset) in CONNECTOR_PLACEMENTS {
+                let building = kind.building(ingress + pos_offset, rotation);
+                let state = SearchState {
+                    egress: ingress + end_offset,
+                    rotation,
+                    kind,
+                };
+                push_node(ctx, Some(node_idx), building, state);
+            }
+        }
     }
 
-    // check if we're already connected to another path leading to the factory
-    if let Some(s) = find_connection_around(ctx, parent_id, connector_id, start_pos, search_depth) {
-        return s;
-    }
+    place_path(ctx, u32::MAX);
+    Err(crate::Error::NoPath(deposit_id, deposit_pos, factory_pos))
+}
 
-    if search_depth == 0 {
-        return (
-            State::Stopped,
-            Some((parent_id, PathStats::new(start_dist, search_depth))),
-        );
+/// Queue a building following the path that is currently placed, if it fits and improves on the
+/// paths found to its state so far
+fn push_node(ctx: &mut Context, parent: Option<u32>, building: Building, state: SearchState) {
+    let Some(Some(dist)) = ctx.distance_map.get(state.egress) else {
+        return;
+    };
+    let parent_stats = parent.map_or(PathStats::default(), |p| ctx.tree.nodes[p as usize].stats);
+    let buildings = parent_stats.buildings + 1;
+    if ctx.objective == RouteObjective::Arrival
+        && ctx
+            .tree
+            .visited
+            .get(&state)
+            .is_some_and(|&c| c.0 <= buildings)
+    {
+        return;
     }
 
-    const DOCKING_POSITIONS: u16 = 3;
-    const SMALL_CONVEYOR_CONFIGURATIONS: u16 = 3;
-    const BIG_CONVEYOR_CONFIGURATIONS: u16 = 3;
-    const COMBINER_CONFIGURATIONS: u16 = 5;
-    const MAX_CHILDREN_LEN: u16 = DOCKING_POSITIONS
-        * (SMALL_CONVEYOR_CONFIGURATIONS + BIG_CONVEYOR_CONFIGURATIONS + COMBINER_CONFIGURATIONS);
-
-    let children_id = ctx.tree.alloc(MAX_CHILDREN_LEN);
-    let mut len = 0;
-
-    let mut best = None;
-
-    #[rustfmt::skip]
-    place_connectors(ctx, start_pos + (-1, 0), children_id, &mut len, &mut best, search_depth);
-    #[rustfmt::skip]
-    place_connectors(ctx, start_pos + (1, 0), children_id, &mut len, &mut best, search_depth);
-    #[rustfmt::skip]
-    place_connectors(ctx, start_pos + (0, 1), children_id, &mut len, &mut best, search_depth);
-    #[rustfmt::skip]
-    place_connectors(ctx, start_pos + (0, -1), children_id, &mut len, &mut best, search_depth);
-
-    let state = State::Children {
-        start: children_id,
-        len,
+    let Ok(id) = sim::place_building(ctx.sim, building.clone()) else {
+        return;
     };
-
-    (state, best)
-}
-
-#[inline(always)]
-fn find_connection_around(
-    ctx: &Context,
-    parent_id: NodeId,
-    connector_id: Id,
-    start_pos: Pos,
-    search_depth: u8,
-) -> Option<(State, Option<(NodeId, PathStats)>)> {
-    if let Some(s) = find_connection_at(
-        ctx,
-        parent_id,
-        connector_id,
-        start_pos + (-1, 0),
-        search_depth,
-    ) {
-        return Some(s);
-    }
-    if let Some(s) = find_connection_at(
-        ctx,
-        parent_id,
-        connector_id,
-        start_pos + (1, 0),
-        search_depth,
-    ) {
-        return Some(s);
-    }
-    if let Some(s) = find_connection_at(
-        ctx,
-        parent_id,
-        connector_id,
-        start_pos + (0, -1),
-        search_depth,
-    ) {
-        return Some(s);
+    let connection = ctx.sim.connections.iter().find(|c| c.output_id == id);
+    let connected = match connection {
+        Some(c) => leads_to(ctx.sim, c.input_id, ctx.factory_id),
+        None => false,
+    };
+    let dead_end = connection.is_some() && !connected;
+    // resources merged into an existing path pass its buildings as well
+    let merged = match connection {
+        Some(c) if connected => path_from(ctx.sim, c.input_id).len() as u16 - 1,
+        _ => 0,
+    };
+    // crossed cells are already covered by the other conveyor
+    let stats = PathStats {
+        buildings: buildings + merged,
+        cells: parent_stats.cells + sim::covered_cells(ctx.sim, id) as u16,
+    };
+    sim::remove_building(ctx.sim, id);
+    // resources would be led away from the factory
+    if dead_end {
+        return;
     }
-    if let Some(s) = find_connection_at(
-        ctx,
-        parent_id,
-        connector_id,
-        start_pos + (0, 1),
-        search_depth,
-    ) {
-        return Some(s);
+    let cost = stats.cost(ctx.objective);
+    if ctx.tree.visited.get(&state).is_some_and(|&c| c <= cost) {
+        return;
     }
 
-    None
-}
-
-#[inline(always)]
-fn find_connection_at(
-    ctx: &Context,
-    parent_id: NodeId,
-    connector_id: Id,
-    pos: Pos,
-    search_depth: u8,
-) -> Option<(State, Option<(NodeId, PathStats)>)> {
-    if let Some(Some(cell)) = ctx.sim.board.get(pos) {
-        if cell.id != connector_id && cell.kind == CellKind::Input {
-            if let Some(s) = find_connection(ctx, parent_id, connector_id, cell.id, search_depth) {
-                return Some(s);
-            }
-        }
+    let (remaining_buildings, remaining_cells) = match connected {
+        true => (0, 0),
+        false => (dist.div_ceil(4), dist.div_ceil(2)),
+    };
+    // paths that can't deliver anything before the turn limit are useless
+    let min_buildings = stats.buildings + remaining_buildings;
+    if sim::arrival_turn(min_buildings as u32) >= ctx.sim.turns {
+        return;
     }
 
-    None
+    ctx.tree.visited.insert(state, cost);
+    let node_idx = ctx.tree.nodes.len() as u32;
+    ctx.tree.nodes.push(ConnectionTreeNode {
+        building,
+        state,
+        parent,
+        stats,
+        connected,
+    });
+    let min_cells = stats.cells + remaining_cells;
+    let priority = match ctx.objective {
+        RouteObjective::Arrival => Priority::new((min_buildings, 0), remaining_buildings),
+        RouteObjective::Footprint => Priority::new((min_cells, min_buildings), remaining_cells),
+    };
+    ctx.tree.open.push((priority, Reverse(node_idx)));
 }
 
-#[inline(always)]
-fn find_connection(
-    ctx: &Context,
-    parent_id: NodeId,
-    connector_id: Id,
-    mut current_id: Id,
-    mut search_depth: u8,
-) -> Option<(State, Option<(NodeId, PathStats)>)> {
-    let mut path = SmallVec::<[_; 15]>::new();
-    let mut last_search_node = connector_id;
-
-    'path: loop {
-        path.push(current_id);
-
-        if search_depth > 0 {
-            last_search_node = current_id;
-            search_depth -= 1;
+/// Whether resources put into a building reach the target, connectors only output into their
+/// first connection
+fn leads_to(sim: &Sim, mut id: Id, target: Id) -> bool {
+    for _ in 0..=sim.connections.len() {
+        if id == target {
+            return true;
         }
-
-        for conn in ctx.sim.connections.iter() {
-            if conn.output_id == current_id {
-                if path.contains(&conn.input_id) {
-                    // we're in a loop
-                    return None;
-                }
-
-                current_id = conn.input_id;
-
-                if current_id == ctx.factory_id {
-                    let last_building = &ctx.sim.buildings[last_search_node];
-                    let dist = match last_building {
-                        Building::Deposit(_) => unreachable!(),
-                        Building::Obstacle(_) => unreachable!(),
-                        Building::Mine(mine) => {
-                            let pos = mine.pos
-                                + match mine.rotation {
-                                    Rotation::Right => (2, 1),
-                                    Rotation::Down => (0, 2),
-                                    Rotation::Left => (-1, 0),
-                                    Rotation::Up => (1, -1),
-                                };
-                            ctx.distance_map[pos].expect("this field to be valid")
-                        }
-                        Building::Conveyor(conveyor) => {
-                            let pos = conveyor.pos
-                                + if conveyor.big {
-                                    match conveyor.rotation {
-                                        Rotation::Right => (2, 0),
-                                        Rotation::Down => (0, 2),
-                                        Rotation::Left => (-1, 0),
-                                        Rotation::Up => (0, -1),
-                                    }
-                                } else {
-                                    match conveyor.rotation {
-                                        Rotation::Right => (1, 0),
-                                        Rotation::Down => (0, 1),
-                                        Rotation::Left => (-1, 0),
-                                        Rotation::Up => (0, -1),
-                                    }
-                                };
-                            ctx.distance_map[pos].expect("this field to be valid")
-                        }
-                        Building::Combiner(combiner) => {
-                            let pos = combiner.pos
-                                + match combiner.rotation {
-                                    Rotation::Right => (1, 0),
-                                    Rotation::Down => (0, 1),
-                                    Rotation::Left => (-1, 0),
-                                    Rotation::Up => (0, -1),
-                                };
-                            ctx.distance_map[pos].expect("this field to be valid")
-                        }
-                        Building::Factory(_) => 0,
-                    };
-                    return Some((
-                        State::Merged,
-                        Some((parent_id, PathStats::new(dist, search_depth))),
-                    ));
-                }
-
-                continue 'path;
-            }
+        match sim.connections.iter().find(|c| c.output_id == id) {
+            Some(c) => id = c.input_id,
+            None => return false,
         }
-
-        // the path ends here
-        return None;
     }
+    // the path runs in a circle
+    false
 }
 
-/// Place conveyors or combiners
-#[rustfmt::skip]
-#[inline(always)]
-fn place_connectors(
-    ctx: &mut Context,
-    start_pos: Pos,
-    children_id: ChildrenId,
-    len: &mut u16,
-    best: &mut Option<(NodeId, PathStats)>,
-    search_depth: u8,
-) {
-    // small conveyors
-    let stats = place_conveyor(ctx, start_pos, children_id, len, search_depth, Rotation::Right, (1,  0), (2,  0), false);
-    cmp_and_set(best, stats);
-    let stats = place_conveyor(ctx, start_pos, children_id, len, search_depth, Rotation::Down,  (0,  1), (0,  2), false);
-    cmp_and_set(best, stats);
-    let stats = place_conveyor(ctx, start_pos, children_id, len, search_depth, Rotation::Left,  (-1, 0), (-2, 0), false);
-    cmp_and_set(best, stats);
-    let stats = place_conveyor(ctx, start_pos, children_id, len, search_depth, Rotation::Up,    (0, -1), (0, -2), false);
-    cmp_and_set(best, stats);
-
-    // big conveyors
-    let stats = place_conveyor(ctx, start_pos, children_id, len, search_depth, Rotation::Right, (1,  0), (3,  0), true);
-    cmp_and_set(best, stats);
-    let stats = place_conveyor(ctx, start_pos, children_id, len, search_depth, Rotation::Down,  (0,  1), (0,  3), true);
-    cmp_and_set(best, stats);
-    let stats = place_conveyor(ctx, start_pos, children_id, len, search_depth, Rotation::Left,  (-2, 0), (-3, 0), true);
-    cmp_and_set(best, stats);
-    let stats = place_conveyor(ctx, start_pos, children_id, len, search_depth, Rotation::Up,    (0, -2), (0, -3), true);
-    cmp_and_set(best, stats);
-
-    // combiners
-    let stats = place_combiner(ctx, start_pos, children_id, len, search_depth, Rotation::Right, (1,  1), (2,  1));
-    cmp_and_set(best, stats);
-    let stats = place_combiner(ctx, start_pos, children_id, len, search_depth, Rotation::Right, (1,  0), (2,  0));
-    cmp_and_set(best, stats);
-    let stats = place_combiner(ctx, start_pos, children_id, len, search_depth, Rotation::Right, (1, -1), (2, -1));
-    cmp_and_set(best, stats);
-
-    let stats = place_combiner(ctx, start_pos, children_id, len, search_depth, Rotation::Down, (1,  1), (1,  2));
-    cmp_and_set(best, stats);
-    let stats = place_combiner(ctx, start_pos, children_id, len, search_depth, Rotation::Down, (0,  1), (0,  2));
-    cmp_and_set(best, stats);
-    let stats = place_combiner(ctx, start_pos, children_id, len, search_depth, Rotation::Down, (-1, 1), (-1, 2));
-    cmp_and_set(best, stats);
-
-    let stats = place_combiner(ctx, start_pos, children_id, len, search_depth, Rotation::Left, (-1,  1), (-2,  1));
-    cmp_and_set(best, stats);
-    let stats = place_combiner(ctx, start_pos, children_id, len, search_depth, Rotation::Left, (-1,  0), (-2,  0));
-    cmp_and_set(best, stats);
-    let stats = place_combiner(ctx, start_pos, children_id, len, search_depth, Rotation::Left, (-1, -1), (-2, -1));
-    cmp_and_set(best, stats);
-
-    let stats = place_combiner(ctx, start_pos, children_id, len, search_depth, Rotation::Up, (1,  -1), (1,  -2));
-    cmp_and_set(best, stats);
-    let stats = place_combiner(ctx, start_pos, children_id, len, search_depth, Rotation::Up, (0,  -1), (0,  -2));
-    cmp_and_set(best, stats);
-    let stats = place_combiner(ctx, start_pos, children_id, len, search_depth, Rotation::Up, (-1, -1), (-1, -2));
-    cmp_and_set(best, stats);
-}
-
-fn place_conveyor(
-    ctx: &mut Context,
-    start_pos: Pos,
-    children_id: ChildrenId,
-    len: &mut u16,
-    search_depth: u8,
-    rotation: Rotation,
-    pos_offset: impl Into<Pos>,
-    end_offset: impl Into<Pos>,
-    big: bool,
-) -> Option<(NodeId, PathStats)> {
-    let end_pos = start_pos + end_offset;
-    let end_dist = ctx.distance_map.get(end_pos).flatten()?;
-    let conveyor = Conveyor::new(start_pos + pos_offset, rotation, big);
-    let building_id = sim::place_building(ctx.sim, Building::Conveyor(conveyor)).ok()?;
-
-    let node_id = increment_id(children_id, len);
-
-    #[rustfmt::skip]
-    let (state, stats) = place_children_connectors(ctx, building_id, node_id, end_pos, end_dist, search_depth - 1);
-
-    sim::remove_building(ctx.sim, building_id);
-
-    let building = ConnectionBuilding::Conveyor(conveyor);
-    let node = ConnectionTreeNode::new(building, start_pos, end_pos, state);
-    ctx.tree[node_id] = node;
-
-    stats.map(|(_, s)| (node_id, s))
-}
-
-fn place_combiner(
-    ctx: &mut Context,
-    start_pos: Pos,
-    children_id: ChildrenId,
-    len: &mut u16,
-    search_depth: u8,
-    rotation: Rotation,
-    pos_offset: impl Into<Pos>,
-    end_offset: impl Into<Pos>,
-) -> Option<(NodeId, PathStats)> {
-    let end_pos = start_pos + end_offset;
-    let end_dist = ctx.distance_map.get(end_pos).flatten()?;
-    let combiner = Combiner::new(start_pos + pos_offset, rotation);
-    let building_id = sim::place_building(ctx.sim, Building::Combiner(combiner)).ok()?;
-
-    let node_id = increment_id(children_id, len);
-
-    #[rustfmt::skip]
-    let (state, stats) = place_children_connectors(ctx, building_id, node_id, end_pos, end_dist, search_depth - 1);
-
-    sim::remove_building(ctx.sim, building_id);
-
-    let building = ConnectionBuilding::Combiner(combiner);
-    let node = ConnectionTreeNode::new(building, start_pos, end_pos, state);
-    ctx.tree[node_id] = node;
-
-    stats.map(|(_, s)| (node_id, s))
-}
-
-#[inline(always)]
-fn cmp_and_set(best: &mut Option<(NodeId, PathStats)>, other: Option<(NodeId, PathStats)>) {
-    if let Some((_, other_stats)) = &other {
-        match best {
-            None => *best = other,
-            Some((_, best_stats)) => {
-                if other_stats > best_stats {
-                    *best = other;
-                }
-            }
-        }
+/// Replace the path that is placed on the board by the path leading to the node, only the
+/// buildings after the common start are removed and placed. An index past the nodes removes the
+/// whole path.
+fn place_path(ctx: &mut Context, node_idx: u32) {
+    let mut path = Vec::new();
+    let mut current = ctx.tree.nodes.get(node_idx as usize).map(|_| node_idx);
+    while let Some(idx) = current {
+        path.push(idx);
+        current = ctx.tree.nodes[idx as usize].parent;
+    }
+    path.reverse();
+
+    let common = ctx
+        .tree
+        .placed
+        .iter()
+        .zip(path.iter())
+        .take_while(|((placed, _), idx)| placed == *idx)
+        .count();
+    while ctx.tree.placed.len() > common {
+        let (_, id) = ctx.tree.placed.pop().expect("placed path not to be empty");
+        sim::remove_building(ctx.sim, id);
+    }
+    for &idx in path[common..].iter() {
+        let building = ctx.tree.nodes[idx as usize].building.clone();
+        let id = sim::place_building(ctx.sim, building).expect("path to fit as when it was queued");
+        ctx.tree.placed.push((idx, id));
     }
 }
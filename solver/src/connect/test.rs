@@ -1,50 +1,360 @@
-use sim::{Board, ProductType, Products};
+use sim::{Board, Deposit, Obstacle, ProductType, Products, ResourceType};
+
+use crate::{CancelToken, RouteObjective, SolverConfig};
 
 use super::*;
 
+fn connectors(sim: &Sim) -> usize {
+    sim.buildings
+        .iter()
+        .filter(|(_, b)| {
+            matches!(
+                b,
+                Building::Mine(_) | Building::Conveyor(_) | Building::Combiner(_)
+            )
+        })
+        .count()
+}
+
+fn mine_id(sim: &Sim) -> Id {
+    sim.buildings
+        .iter()
+        .find(|(_, b)| matches!(b, Building::Mine(_)))
+        .map(|(id, _)| id)
+        .unwrap()
+}
+
 #[test]
-fn path_stats() {
-    assert!(PathStats::new(1, 0) > PathStats::new(4, 0));
-    assert!(PathStats::new(1, 0) == PathStats::new(1, 0));
-    assert!(PathStats::new(2, 0) < PathStats::new(1, 0));
+fn search_priority() {
+    assert!(Priority::new((1, 5), 0) > Priority::new((4, 5), 0));
+    assert!(Priority::new((1, 5), 0) == Priority::new((1, 5), 0));
+    assert!(Priority::new((2, 5), 0) < Priority::new((1, 5), 0));
 
-    assert!(PathStats::new(1, 3) > PathStats::new(1, 1));
-    assert!(PathStats::new(1, 2) == PathStats::new(1, 2));
-    assert!(PathStats::new(1, 1) < PathStats::new(1, 3));
+    // ties are broken by the second metric, then by the remaining cost
+    assert!(Priority::new((3, 5), 2) > Priority::new((3, 6), 1));
+    assert!(Priority::new((3, 5), 1) > Priority::new((3, 5), 2));
+    assert!(Priority::new((3, 5), 2) < Priority::new((3, 5), 1));
 }
 
 #[test]
-fn find_conveyor_connection_around() {
-    let board = Board::new(20, 20);
-    let products = Products::default();
-    let mut sim = Sim::new(products, board, 20, 20.0);
+fn route_with_the_fewest_buildings() {
+    let mut sim = Sim::new(Products::default(), Board::new(20, 10), 20, 20.0);
+    let deposit = Building::Deposit(Deposit::new((0, 0), 3, 3, ResourceType::Type0));
+    let deposit_id = sim::place_building(&mut sim, deposit).unwrap();
+    let factory = Factory::new((9, 0), ProductType::Type0);
+    let factory_id = sim::place_building(&mut sim, Building::Factory(factory)).unwrap();
+
+    let mut tree = ConnectionTree::new();
+    reconnect_deposit(
+        &mut sim,
+        &mut tree,
+        deposit_id,
+        factory_id,
+        &SolverConfig::default(),
+        &CancelToken::new(),
+    )
+    .unwrap();
 
+    // a mine can't reach the factory on its own, but a mine and a conveyor can
+    assert_eq!(connectors(&sim), 2);
+    assert!(leads_to(&sim, mine_id(&sim), factory_id));
+}
+
+#[test]
+fn route_merges_into_existing_path() {
+    let mut sim = Sim::new(Products::default(), Board::new(30, 20), 20, 20.0);
     let factory = Factory::new((4, 2), ProductType::Type0);
     let factory_id = sim::place_building(&mut sim, Building::Factory(factory)).unwrap();
 
-    let distance_map = map_distances(&sim, factory.pos, FACTORY_SIZE, FACTORY_SIZE);
+    let building = Building::Conveyor(Conveyor::new((10, 3), Rotation::Left, false));
+    sim::place_building(&mut sim, building).unwrap();
+    let building = Building::Conveyor(Conveyor::new((13, 3), Rotation::Left, false));
+    sim::place_building(&mut sim, building).unwrap();
+
+    let deposit = Building::Deposit(Deposit::new((19, 2), 2, 2, ResourceType::Type0));
+    let deposit_id = sim::place_building(&mut sim, deposit).unwrap();
+
     let mut tree = ConnectionTree::new();
-    let ctx = Context {
-        sim: &mut sim,
-        tree: &mut tree,
-        distance_map,
+    let s = reconnect_deposit(
+        &mut sim,
+        &mut tree,
+        deposit_id,
         factory_id,
+        &SolverConfig::default(),
+        &CancelToken::new(),
+    )
+    .unwrap();
+
+    // a single mine leads into the existing conveyors, resources pass all three
+    assert_eq!(connectors(&sim), 3);
+    assert!(leads_to(&sim, mine_id(&sim), factory_id));
+    assert_eq!(s.buildings, 3);
+    assert_eq!(measure_paths(&sim)[0].stats.buildings, 3);
+}
+
+#[test]
+fn route_crosses_conveyors() {
+    let mut sim = Sim::new(Products::default(), Board::new(20, 12), 30, 20.0);
+    let deposit = Building::Deposit(Deposit::new((0, 4), 3, 3, ResourceType::Type0));
+    let deposit_id = sim::place_building(&mut sim, deposit).unwrap();
+    let factory = Factory::new((14, 3), ProductType::Type0);
+    let factory_id = sim::place_building(&mut sim, Building::Factory(factory)).unwrap();
+
+    // a wall of conveyors across the whole board
+    for y in [1, 4, 7, 10] {
+        let building = Building::Conveyor(Conveyor::new((8, y), Rotation::Down, false));
+        sim::place_building(&mut sim, building).unwrap();
+    }
+
+    let mut tree = ConnectionTree::new();
+    reconnect_deposit(
+        &mut sim,
+        &mut tree,
+        deposit_id,
+        factory_id,
+        &SolverConfig::default(),
+        &CancelToken::new(),
+    )
+    .unwrap();
+    assert!(leads_to(&sim, mine_id(&sim), factory_id));
+}
+
+#[test]
+fn no_route_leaves_board_unchanged() {
+    let mut sim = Sim::new(Products::default(), Board::new(30, 10), 5, 20.0);
+    let deposit = Building::Deposit(Deposit::new((0, 0), 3, 3, ResourceType::Type0));
+    let deposit_id = sim::place_building(&mut sim, deposit).unwrap();
+    let factory = Factory::new((20, 0), ProductType::Type0);
+    let factory_id = sim::place_building(&mut sim, Building::Factory(factory)).unwrap();
+    let before = sim.clone();
+
+    // too far to deliver anything within 5 turns
+    let mut tree = ConnectionTree::new();
+    let res = reconnect_deposit(
+        &mut sim,
+        &mut tree,
+        deposit_id,
+        factory_id,
+        &SolverConfig::default(),
+        &CancelToken::new(),
+    );
+    assert!(res.is_err());
+    assert_eq!(sim.board, before.board);
+    assert_eq!(connectors(&sim), 0);
+}
+
+#[test]
+fn route_objectives() {
+    for (x, y) in [(8, 3), (8, 7), (10, 0), (14, 6), (20, 12)] {
+        let mut stats = Vec::new();
+        for route_objective in [RouteObjective::Arrival, RouteObjective::Footprint] {
+            let mut sim = Sim::new(Products::default(), Board::new(40, 20), 40, 20.0);
+            let deposit = Building::Deposit(Deposit::new((0, 0), 3, 3, ResourceType::Type0));
+            let deposit_id = sim::place_building(&mut sim, deposit).unwrap();
+            let factory = Factory::new((x, y), ProductType::Type0);
+            let factory_id = sim::place_building(&mut sim, Building::Factory(factory)).unwrap();
+
+            let config = SolverConfig {
+                route_objective,
+                ..SolverConfig::default()
+            };
+            let mut tree = ConnectionTree::new();
+            let cancel = CancelToken::new();
+            let s = reconnect_deposit(
+                &mut sim, &mut tree, deposit_id, factory_id, &config, &cancel,
+            );
+            let s = s.unwrap();
+
+            // the reported metrics match the placed path
+            let cells = (0..sim.board.height)
+                .flat_map(|y| (0..sim.board.width).map(move |x| pos(x, y)))
+                .filter_map(|p| sim.board[p])
+                .filter(|c| c.id != deposit_id && c.id != factory_id)
+                .count();
+            assert_eq!(s.buildings as usize, connectors(&sim));
+            assert_eq!(s.cells as usize, cells);
+            let measured = measure_paths(&sim);
+            assert_eq!(measured.len(), 1);
+            assert_eq!(measured[0].factory_id, factory_id);
+            assert_eq!(measured[0].stats, s);
+            stats.push(s);
+        }
+
+        let (arrival, footprint) = (stats[0], stats[1]);
+        assert!(arrival.buildings <= footprint.buildings);
+        assert!(footprint.cells <= arrival.cells);
+    }
+}
+
+#[test]
+fn crossing_paths() {
+    let mut sim = Sim::new(Products::default(), Board::new(24, 12), 40, 20.0);
+    let deposit = Building::Deposit(Deposit::new((0, 0), 3, 3, ResourceType::Type0));
+    let top_deposit_id = sim::place_building(&mut sim, deposit).unwrap();
+    let deposit = Building::Deposit(Deposit::new((0, 9), 3, 3, ResourceType::Type1));
+    let bottom_deposit_id = sim::place_building(&mut sim, deposit).unwrap();
+    let factory = Factory::new((19, 7), ProductType::Type0);
+    let bottom_factory_id = sim::place_building(&mut sim, Building::Factory(factory)).unwrap();
+    let factory = Factory::new((19, 0), ProductType::Type1);
+    let top_factory_id = sim::place_building(&mut sim, Building::Factory(factory)).unwrap();
+
+    let config = SolverConfig {
+        route_objective: RouteObjective::Footprint,
+        ..SolverConfig::default()
     };
+    let mut tree = ConnectionTree::new();
+    let first = reconnect_deposit(
+        &mut sim,
+        &mut tree,
+        top_deposit_id,
+        bottom_factory_id,
+        &config,
+        &CancelToken::new(),
+    )
+    .unwrap();
+    let before: Vec<Id> = sim.buildings.iter().map(|(id, _)| id).collect();
+    tree.clear();
+    let s = reconnect_deposit(
+        &mut sim,
+        &mut tree,
+        bottom_deposit_id,
+        top_factory_id,
+        &config,
+        &CancelToken::new(),
+    )
+    .unwrap();
 
-    let building = Building::Conveyor(Conveyor::new((10, 3), Rotation::Left, false));
-    sim::place_building(ctx.sim, building).unwrap();
+    // the paths can't go around each other
+    let mines: Vec<Id> = sim
+        .buildings
+        .iter()
+        .filter(|(_, b)| matches!(b, Building::Mine(_)))
+        .map(|(id, _)| id)
+        .collect();
+    assert_eq!(mines.len(), 2);
+    assert!(leads_to(&sim, mines[0], bottom_factory_id));
+    assert!(leads_to(&sim, mines[1], top_factory_id));
 
-    let building = Building::Conveyor(Conveyor::new((13, 3), Rotation::Left, false));
-    let conveyor_id = sim::place_building(ctx.sim, building).unwrap();
-
-    let search_depth = 2;
-    let parent_id = NodeId(324);
-    let (state, stats) =
-        find_connection_around(&ctx, parent_id, conveyor_id, Pos::new(12, 3), search_depth)
-            .unwrap();
-    assert_eq!(state, State::Merged);
-    assert_eq!(
-        stats,
-        Some((parent_id, PathStats::new(0, search_depth - 1)))
+    // conveyors of the second path share the crossed cells with the first
+    let crossings = sim
+        .buildings
+        .iter()
+        .filter(|(id, _)| !before.contains(id))
+        .filter(|(id, b)| match b {
+            Building::Conveyor(c) => sim::covered_cells(&sim, *id) < 3 + c.big as u32,
+            _ => false,
+        })
+        .count();
+    assert!(crossings > 0);
+
+    // the shared cells are counted once
+    let cells: u32 = sim
+        .buildings
+        .iter()
+        .filter(|(id, _)| !before.contains(id))
+        .map(|(id, _)| sim::covered_cells(&sim, id))
+        .sum();
+    assert_eq!(s.cells as u32, cells);
+
+    // both paths are reported with the metrics the search found for them
+    let measured = measure_paths(&sim);
+    let stats = |factory_id| {
+        let path = measured.iter().find(|p| p.factory_id == factory_id);
+        path.map(|p| p.stats)
+    };
+    assert_eq!(stats(bottom_factory_id), Some(first));
+    assert_eq!(stats(top_factory_id), Some(s));
+}
+
+#[test]
+fn cross_two_conveyors_at_once() {
+    let mut sim = Sim::new(Products::default(), Board::new(24, 16), 60, 20.0);
+    let deposit = Building::Deposit(Deposit::new((0, 6), 3, 3, ResourceType::Type0));
+    let deposit_id = sim::place_building(&mut sim, deposit).unwrap();
+    let factory = Factory::new((19, 8), ProductType::Type0);
+    let factory_id = sim::place_building(&mut sim, Building::Factory(factory)).unwrap();
+    // pairs of neighboring conveyor lines, the path has to cross them to reach the factory
+    for x in [8, 9, 12, 13, 16, 17] {
+        for y in [1, 5, 9] {
+            let conveyor = Conveyor::new((x, y), Rotation::Down, true);
+            sim::place_building(&mut sim, Building::Conveyor(conveyor)).unwrap();
+        }
+    }
+    let before: Vec<Id> = sim.buildings.iter().map(|(id, _)| id).collect();
+
+    let config = SolverConfig {
+        route_objective: RouteObjective::Footprint,
+        ..SolverConfig::default()
+    };
+    let mut tree = ConnectionTree::new();
+    let s = reconnect_deposit(
+        &mut sim,
+        &mut tree,
+        deposit_id,
+        factory_id,
+        &config,
+        &CancelToken::new(),
+    )
+    .unwrap();
+
+    // big conveyors crossing both lines of a pair only cover their ingress and egress, which
+    // the search only finds if it doesn't overestimate the cells still needed
+    let path: Vec<Id> = sim
+        .buildings
+        .iter()
+        .map(|(id, _)| id)
+        .filter(|id| !before.contains(id))
+        .collect();
+    let cells: u32 = path.iter().map(|&id| sim::covered_cells(&sim, id)).sum();
+    assert_eq!(s.cells as u32, cells);
+    assert_eq!(s.cells, 15);
+    assert!(path.iter().any(|&id| {
+        matches!(sim.buildings[id], Building::Conveyor(c) if c.big)
+            && sim::covered_cells(&sim, id) == 2
+    }));
+}
+
+#[test]
+fn merge_only_into_paths_that_arrive_early() {
+    let mut sim = Sim::new(Products::default(), Board::new(30, 30), 100, 20.0);
+    let factory = Factory::new((20, 0), ProductType::Type0);
+    let factory_id = sim::place_building(&mut sim, Building::Factory(factory)).unwrap();
+    let deposit = Building::Deposit(Deposit::new((0, 0), 3, 3, ResourceType::Type0));
+    let far_deposit_id = sim::place_building(&mut sim, deposit).unwrap();
+    let deposit = Building::Deposit(Deposit::new((0, 6), 3, 3, ResourceType::Type0));
+    let near_deposit_id = sim::place_building(&mut sim, deposit).unwrap();
+
+    // the first path makes a long detour around a wall that's gone afterwards
+    let wall = Building::Obstacle(Obstacle::new((10, 0), 1, 28));
+    let wall_id = sim::place_building(&mut sim, wall).unwrap();
+    let config = SolverConfig::default();
+    let mut tree = ConnectionTree::new();
+    let cancel = CancelToken::new();
+    let detour = reconnect_deposit(
+        &mut sim,
+        &mut tree,
+        far_deposit_id,
+        factory_id,
+        &config,
+        &cancel,
     );
+    let detour = detour.unwrap();
+    sim::remove_building(&mut sim, wall_id);
+
+    tree.clear();
+    let s = reconnect_deposit(
+        &mut sim,
+        &mut tree,
+        near_deposit_id,
+        factory_id,
+        &config,
+        &cancel,
+    );
+    let s = s.unwrap();
+
+    // merging into the first path saves buildings, but resources would take the detour
+    assert!(s.buildings < detour.buildings);
+    let measured = measure_paths(&sim);
+    assert_eq!(measured.len(), 2);
+    assert!(measured.iter().any(|p| p.stats == s));
 }
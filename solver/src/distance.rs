@@ -1,5 +1,5 @@
 use core::fmt;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
 use sim::{pos, Building, Id, Pos, Sim};
 
@@ -89,56 +89,95 @@ impl DistanceMap {
 }
 
 pub fn map_deposit_distances(sim: &Sim) -> HashMap<Id, DistanceMap> {
+    let mut queue = VecDeque::new();
     sim.buildings
         .iter()
         .filter_map(|(i, b)| {
             let Building::Deposit(deposit) = b else { return None };
-            let map = map_distances(sim, deposit.pos, deposit.width as i8, deposit.height as i8);
+            let (width, height) = (deposit.width as i8, deposit.height as i8);
+            let map = map_distances_with(sim, &mut queue, deposit.pos, width, height);
             Some((i, map))
         })
         .collect()
 }
 
-/// Generate a map of Manhattan distances to a rectangular object
+/// A copy of the sim without mines, conveyors and combiners on the board, new paths can cross
+/// and merge into them
+pub(crate) fn without_connectors(sim: &Sim) -> Sim {
+    let mut base = sim.clone();
+    for y in 0..base.board.height {
+        for x in 0..base.board.width {
+            let Some(cell) = base.board[pos(x, y)] else {
+                continue;
+            };
+            if matches!(
+                sim.buildings[cell.id],
+                Building::Mine(_) | Building::Conveyor(_) | Building::Combiner(_)
+            ) {
+                base.board[pos(x, y)] = None;
+            }
+        }
+    }
+    base
+}
+
+/// The minimum distance of all cells on the border of a rectangular object
+pub fn border_distance(map: &DistanceMap, pos: Pos, width: i8, height: i8) -> Option<u16> {
+    border_cells(pos, width, height)
+        .filter_map(|p| map.get(p).flatten())
+        .min()
+}
+
+/// The cells on the border of a rectangular object
+pub fn border_cells(pos: Pos, width: i8, height: i8) -> impl Iterator<Item = Pos> {
+    let top_bottom = (0..width).flat_map(move |x| [pos + (x, 0), pos + (x, height - 1)]);
+    let left_right = (1..height - 1).flat_map(move |y| [pos + (0, y), pos + (width - 1, y)]);
+    top_bottom.chain(left_right)
+}
+
+/// Generate a map of Manhattan distances to a rectangular object, going around occupied cells
 pub fn map_distances(sim: &Sim, pos: Pos, width: i8, height: i8) -> DistanceMap {
+    map_distances_with(sim, &mut VecDeque::new(), pos, width, height)
+}
+
+/// Like [`map_distances`], reusing the `queue` of the breadth first search between calls
+pub fn map_distances_with(
+    sim: &Sim,
+    queue: &mut VecDeque<Pos>,
+    pos: Pos,
+    width: i8,
+    height: i8,
+) -> DistanceMap {
     let mut map = DistanceMap::new(sim.board.width, sim.board.height);
+    queue.clear();
 
-    for i in 0..width {
-        let pos = pos + (i, -1);
-        map_distance(sim, &mut map, pos, 0);
-    }
-    for i in 0..width {
-        let pos = pos + (i, height);
-        map_distance(sim, &mut map, pos, 0);
+    let top_bottom = (0..width).flat_map(|x| [pos + (x, -1), pos + (x, height)]);
+    let left_right = (0..height).flat_map(|y| [pos + (-1, y), pos + (width, y)]);
+    for p in top_bottom.chain(left_right) {
+        visit(sim, &mut map, queue, p, 0);
     }
-    for i in 0..height {
-        let pos = pos + (-1, i);
-        map_distance(sim, &mut map, pos, 0);
-    }
-    for i in 0..height {
-        let pos = pos + (height, i);
-        map_distance(sim, &mut map, pos, 0);
+
+    while let Some(p) = queue.pop_front() {
+        let dist = map[p].expect("queued cells to be visited") + 1;
+        visit(sim, &mut map, queue, p + (0, -1), dist);
+        visit(sim, &mut map, queue, p + (-1, 0), dist);
+        visit(sim, &mut map, queue, p + (0, 1), dist);
+        visit(sim, &mut map, queue, p + (1, 0), dist);
     }
 
     map
 }
 
-fn map_distance(sim: &Sim, map: &mut DistanceMap, pos: Pos, new_dist: u16) {
+/// Cells are visited in order of their distance, so the first visit is the shortest
+fn visit(sim: &Sim, map: &mut DistanceMap, queue: &mut VecDeque<Pos>, pos: Pos, dist: u16) {
     // Out of bounds
     let Some(val) = map.get_mut(pos) else { return };
 
-    // Occupied
-    if sim.board[pos].is_some() {
+    // Occupied or already visited
+    if sim.board[pos].is_some() || val.is_some() {
         return;
     }
 
-    match val {
-        Some(current_dist) if *current_dist <= new_dist => return,
-        Some(_) | None => *val = Some(new_dist),
-    }
-
-    map_distance(sim, map, pos + (0, -1), new_dist + 1);
-    map_distance(sim, map, pos + (-1, 0), new_dist + 1);
-    map_distance(sim, map, pos + (0, 1), new_dist + 1);
-    map_distance(sim, map, pos + (1, 0), new_dist + 1);
+    *val = Some(dist);
+    queue.push_back(pos);
 }
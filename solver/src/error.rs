@@ -1,15 +1,17 @@
 use core::fmt;
 
-use sim::{Id, Pos};
+use sim::{dto, Id, Pos};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Error {
     Sim(sim::Error),
+    Task(dto::TaskError),
     NoPath(Id, Pos, Pos),
     /// TODO: proper error
     NoSolution,
+    InvalidConfig(&'static str),
 }
 
 impl std::error::Error for Error {}
@@ -18,11 +20,13 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::Sim(e) => write!(f, "{e}"),
+            Error::Task(e) => write!(f, "{e}"),
             Error::NoPath(d_id, d_pos, f_pos) => write!(
                 f,
                 "No path found between deposit {d_id:?} at {d_pos} and factory at {f_pos}"
             ),
             Error::NoSolution => write!(f, "No solution"),
+            Error::InvalidConfig(msg) => write!(f, "Invalid solver config: {msg}"),
         }
     }
 }
@@ -32,3 +36,9 @@ impl From<sim::Error> for Error {
         Self::Sim(e)
     }
 }
+
+impl From<dto::TaskError> for Error {
+    fn from(e: dto::TaskError) -> Self {
+        Self::Task(e)
+    }
+}
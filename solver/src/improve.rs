@@ -0,0 +1,297 @@
+//! Local search on the best solution, once the connection search is done.
+//!
+//! Small mutations of the solution are scored with [`sim::run`] and accepted by a simulated
+//! annealing schedule. Early on, worse solutions are accepted as well to escape local optima, the
+//! temperature falls linearly until the budget is used up.
+
+use std::sync::{mpsc, Mutex};
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use sim::{Building, Combiner, Evaluator, Id, Rotation, Sim, SimRun};
+
+use crate::combine::publish;
+use crate::{
+    path_from, reconnect_deposit, Budget, CancelToken, ConnectionTree, ScoredSolution, SolverConfig,
+};
+
+const ROTATIONS: [Rotation; 4] = [
+    Rotation::Right,
+    Rotation::Down,
+    Rotation::Left,
+    Rotation::Up,
+];
+const OFFSETS: [(i8, i8); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mutation {
+    /// Move a conveyor or combiner by one cell
+    MoveConnector,
+    /// Rotate a mine, conveyor or combiner
+    RotateConnector,
+    /// Swap a small conveyor for a big one or vice versa
+    ToggleConveyorSize,
+    /// Replace a conveyor with a combiner
+    PlaceCombiner,
+    /// Remove the path of a mine and connect its deposit again
+    RerouteMine,
+    /// Move a factory and connect all deposits that led to it again
+    RelocateFactory,
+}
+
+const MUTATIONS: [Mutation; 6] = [
+    Mutation::MoveConnector,
+    Mutation::RotateConnector,
+    Mutation::ToggleConveyorSize,
+    Mutation::PlaceCombiner,
+    Mutation::RerouteMine,
+    Mutation::RelocateFactory,
+];
+
+/// Improve the best solution until the budget of the improvement phase is used up, prebuilt
+/// buildings of the `task` are left as they are
+pub(crate) fn improve(
+    task: &Sim,
+    best_solution: &Mutex<Option<ScoredSolution>>,
+    improvements: &mpsc::Sender<ScoredSolution>,
+    config: &SolverConfig,
+    start: Instant,
+    cancel: &CancelToken,
+) {
+    let best = best_solution
+        .lock()
+        .expect("lock not to be poisoned")
+        .clone();
+    let Some(mut current) = best else { return };
+    let mut best_run = current.run.clone();
+
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut tree = ConnectionTree::new();
+    let mut evaluator = Evaluator::new();
+    let phase_start = Instant::now();
+    let phase_time = current.sim.time - config.safety_margin - start.elapsed().as_secs_f32();
+    if config.budget == Budget::Time && phase_time <= 0.0 {
+        return;
+    }
+
+    let mut step = 0;
+    loop {
+        let progress = match config.budget {
+            Budget::Time => phase_start.elapsed().as_secs_f32() / phase_time,
+            Budget::Iterations(_) => step as f32 / config.improvement_steps as f32,
+        };
+        if progress.is_nan() || progress >= 1.0 || cancel.is_cancelled() {
+            break;
+        }
+        step += 1;
+
+        let mut sim = current.sim.clone();
+        let mutation = *MUTATIONS
+            .choose(&mut rng)
+            .expect("mutations not to be empty");
+        if !mutate(
+            task, &mut sim, mutation, &mut rng, &mut tree, config, cancel,
+        ) {
+            continue;
+        }
+        let run = evaluator.run(&sim);
+
+        let temperature = config.initial_temperature * (1.0 - progress);
+        let delta = energy(&run, sim.turns) - energy(&current.run, sim.turns);
+        if delta < 0.0 && rng.gen::<f32>() >= (delta / temperature).exp() {
+            continue;
+        }
+
+        current = ScoredSolution::new(sim, run);
+        if current.run > best_run {
+            best_run = current.run.clone();
+            publish(best_solution, improvements, &current);
+        }
+    }
+}
+
+/// Points of a run, with earlier runs scoring a fraction of a point more
+fn energy(run: &SimRun, turns: u32) -> f32 {
+    run.points as f32 + (turns - run.at_turn.min(turns)) as f32 / (turns + 1) as f32
+}
+
+/// Apply the mutation to a random building, returns whether anything was changed
+fn mutate(
+    task: &Sim,
+    sim: &mut Sim,
+    mutation: Mutation,
+    rng: &mut StdRng,
+    tree: &mut ConnectionTree,
+    config: &SolverConfig,
+    cancel: &CancelToken,
+) -> bool {
+    let candidates = sim
+        .buildings
+        .iter()
+        .filter(|&(id, _)| !is_prebuilt(task, id))
+        .filter(|(_, b)| match mutation {
+            Mutation::MoveConnector => matches!(b, Building::Conveyor(_) | Building::Combiner(_)),
+            Mutation::RotateConnector => matches!(
+                b,
+                Building::Mine(_) | Building::Conveyor(_) | Building::Combiner(_)
+            ),
+            Mutation::ToggleConveyorSize | Mutation::PlaceCombiner => {
+                matches!(b, Building::Conveyor(_))
+            }
+            Mutation::RerouteMine => matches!(b, Building::Mine(_)),
+            Mutation::RelocateFactory => matches!(b, Building::Factory(_)),
+        })
+        .map(|(id, _)| id)
+        .collect::<Vec<_>>();
+    let Some(&id) = candidates.choose(rng) else {
+        return false;
+    };
+
+    match mutation {
+        Mutation::MoveConnector => {
+            let offset = *OFFSETS.choose(rng).expect("offsets not to be empty");
+            let building = match sim::remove_building(sim, id) {
+                Building::Conveyor(mut c) => {
+                    c.pos += offset;
+                    Building::Conveyor(c)
+                }
+                Building::Combiner(mut c) => {
+                    c.pos += offset;
+                    Building::Combiner(c)
+                }
+                _ => unreachable!("This should be a connector"),
+            };
+            sim::place_building(sim, building).is_ok()
+        }
+        Mutation::RotateConnector => {
+            let rotation = *ROTATIONS.choose(rng).expect("rotations not to be empty");
+            let building = match sim::remove_building(sim, id) {
+                Building::Mine(mut m) => {
+                    m.rotation = rotation;
+                    Building::Mine(m)
+                }
+                Building::Conveyor(mut c) => {
+                    c.rotation = rotation;
+                    Building::Conveyor(c)
+                }
+                Building::Combiner(mut c) => {
+                    c.rotation = rotation;
+                    Building::Combiner(c)
+                }
+                _ => unreachable!("This should be a connector"),
+            };
+            sim::place_building(sim, building).is_ok()
+        }
+        Mutation::ToggleConveyorSize => {
+            let Building::Conveyor(mut conveyor) = sim::remove_building(sim, id) else {
+                unreachable!("This should be a conveyor")
+            };
+            conveyor.big = !conveyor.big;
+            sim::place_building(sim, Building::Conveyor(conveyor)).is_ok()
+        }
+        Mutation::PlaceCombiner => {
+            let Building::Conveyor(conveyor) = sim::remove_building(sim, id) else {
+                unreachable!("This should be a conveyor")
+            };
+            let combiner = Combiner::new(conveyor.pos, conveyor.rotation);
+            sim::place_building(sim, Building::Combiner(combiner)).is_ok()
+        }
+        Mutation::RerouteMine => {
+            let Some(deposit_id) = mined_deposit(sim, id) else {
+                return false;
+            };
+            let path = path_from(sim, id);
+            let Some(&factory_id) = path.last() else {
+                return false;
+            };
+            if !matches!(sim.buildings[factory_id], Building::Factory(_)) {
+                return false;
+            }
+
+            // only remove the part of the path no other mine uses
+            let exclusive = path
+                .iter()
+                .enumerate()
+                .take_while(|&(i, &b)| {
+                    i == 0 || (b != factory_id && num_inputs(sim, b) == 1 && !is_prebuilt(task, b))
+                })
+                .map(|(_, &b)| b)
+                .collect::<Vec<_>>();
+            for b in exclusive {
+                sim::remove_building(sim, b);
+            }
+            reconnect_deposit(sim, tree, deposit_id, factory_id, config, cancel).is_ok()
+        }
+        Mutation::RelocateFactory => {
+            let mines = sim
+                .buildings
+                .iter()
+                .filter(|(_, b)| matches!(b, Building::Mine(_)))
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>();
+            let mut deposits = Vec::new();
+            let mut remove = Vec::new();
+            for mine_id in mines {
+                let path = path_from(sim, mine_id);
+                if path.last() != Some(&id) {
+                    continue;
+                }
+                if let Some(deposit_id) = mined_deposit(sim, mine_id) {
+                    if !deposits.contains(&deposit_id) {
+                        deposits.push(deposit_id);
+                    }
+                }
+                for b in path {
+                    if !remove.contains(&b) {
+                        remove.push(b);
+                    }
+                }
+            }
+            if !remove.contains(&id) {
+                remove.push(id);
+            }
+
+            let Building::Factory(mut factory) = sim.buildings[id] else {
+                unreachable!("This should be a factory")
+            };
+            for b in remove {
+                if !is_prebuilt(task, b) {
+                    sim::remove_building(sim, b);
+                }
+            }
+            let offset = *OFFSETS.choose(rng).expect("offsets not to be empty");
+            let distance = rng.gen_range(1..=2);
+            factory.pos += (offset.0 * distance, offset.1 * distance);
+            let Ok(factory_id) = sim::place_building(sim, Building::Factory(factory)) else {
+                return false;
+            };
+
+            // reconnect as many deposits as possible, any that fail just make less points
+            for deposit_id in deposits {
+                let _ = reconnect_deposit(sim, tree, deposit_id, factory_id, config, cancel);
+            }
+            true
+        }
+    }
+}
+
+/// Whether the building was already part of the task, those can't be changed by a solution
+fn is_prebuilt(task: &Sim, id: Id) -> bool {
+    task.buildings.get(id).is_some()
+}
+
+/// The deposit a mine extracts resources from
+fn mined_deposit(sim: &Sim, mine_id: Id) -> Option<Id> {
+    sim.connections
+        .iter()
+        .filter(|c| c.input_id == mine_id)
+        .map(|c| c.output_id)
+        .find(|&id| matches!(sim.buildings[id], Building::Deposit(_)))
+}
+
+/// Number of connections that output into `id`
+fn num_inputs(sim: &Sim, id: Id) -> usize {
+    sim.connections.iter().filter(|c| c.input_id == id).count()
+}
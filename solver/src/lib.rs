@@ -1,107 +1,382 @@
-use std::sync::{mpsc, Mutex};
+use std::collections::VecDeque;
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread::{self, ScopedJoinHandle};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use sim::Sim;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use sim::{dto, Building, Sim};
 
+pub use bound::*;
+pub use cancel::*;
+pub use combine::ScoredSolution;
 use combine::*;
+pub use config::*;
 use connect::*;
+pub use connect::{measure_paths, MeasuredPath, PathStats};
 pub use distance::*;
 pub use error::*;
+use improve::*;
+use pool::*;
 pub use region::*;
 pub use stats::*;
 
+mod bound;
+mod cancel;
 mod combine;
+mod config;
 mod connect;
 mod distance;
 mod error;
+mod improve;
+mod pool;
 mod region;
 mod stats;
 #[cfg(test)]
 mod test;
 
-pub fn solve<'env, 'scope>(
+/// Solve a task, the solution holds the buildings to add to the prebuilt ones of the task. It's
+/// empty if nothing scores any points within the budget.
+pub fn solve(task: &dto::Task, config: SolverConfig) -> Result<dto::Solution> {
+    solve_with(task, config, &CancelToken::new(), |_| ())
+}
+
+/// Solve a task like [`solve`], passing every improved solution to `on_improvement` as soon as it's
+/// found. The solver stops early once `cancel` is cancelled and returns the best solution so far.
+pub fn solve_with(
+    task: &dto::Task,
+    config: SolverConfig,
+    cancel: &CancelToken,
+    mut on_improvement: impl FnMut(&dto::Solution),
+) -> Result<dto::Solution> {
+    let start = Instant::now();
+    config.validate()?;
+    let sim = Sim::try_from(task)?;
+
+    let solution = solve_sim(
+        &sim,
+        config,
+        start,
+        cancel,
+        |solution| on_improvement(&dto::Solution::added(&sim, &solution.sim)),
+        |best| best.map(|solution| dto::Solution::added(&sim, &solution.sim)),
+    );
+    Ok(solution.unwrap_or_else(|| dto::Solution::new(Vec::new())))
+}
+
+/// Solve a task that's already built, with the time limit counting from `start`. Every improved
+/// solution is passed to `on_improvement` right away. Once the budget is used up or `cancel` is
+/// cancelled, the best solution is passed to `f` before waiting for the solver threads to stop, so
+/// that it can be put out in time. `cancel` is only read, the threads are stopped by a token of
+/// their own.
+pub fn solve_sim<R>(
+    sim: &Sim,
+    config: SolverConfig,
+    start: Instant,
+    cancel: &CancelToken,
+    mut on_improvement: impl FnMut(&ScoredSolution),
+    f: impl FnOnce(Option<&ScoredSolution>) -> R,
+) -> R {
+    let best_solution = Mutex::new(None);
+    let (sender, receiver) = mpsc::channel();
+    let stop = cancel.child();
+
+    thread::scope(|s| {
+        let budget = config.budget;
+        let time_limit = Duration::from_secs_f32(sim.time)
+            .saturating_sub(Duration::from_secs_f32(config.safety_margin));
+        let (connect_handle, combine_handle) =
+            spawn(sim, s, &best_solution, sender, config, start, stop.clone());
+
+        // the solver drops the sender once it's done
+        loop {
+            let solution = match budget {
+                Budget::Time => receiver.recv_timeout(time_limit.saturating_sub(start.elapsed())),
+                // wait for the solver to finish, regardless of the time limit
+                Budget::Iterations(_) => receiver
+                    .recv()
+                    .map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+            };
+            match solution {
+                Ok(solution) => on_improvement(&solution),
+                Err(_) => break,
+            }
+        }
+        drop(receiver);
+
+        // the solver threads may still be running after the time limit, don't keep them waiting
+        // for the lock while the solution is put out
+        let best = best_solution
+            .lock()
+            .expect("lock not to be poisoned")
+            .clone();
+        stop.cancel();
+        let res = f(best.as_ref());
+
+        connect_handle.join().expect("connect thread not to panic");
+        combine_handle.join().expect("combine thread not to panic");
+
+        res
+    })
+}
+
+/// Search solutions on scoped threads, the best one found so far is kept in `best_solution`. Every
+/// time it improves, it's also sent to `improvements`. The threads finish when the budget is used
+/// up or `cancel` is cancelled.
+fn spawn<'env, 'scope>(
     sim: &'env Sim,
     scope: &'scope thread::Scope<'scope, 'env>,
     best_solution: &'scope Mutex<Option<ScoredSolution>>,
+    improvements: mpsc::Sender<ScoredSolution>,
+    config: SolverConfig,
     start: Instant,
+    cancel: CancelToken,
 ) -> (ScopedJoinHandle<'scope, ()>, ScopedJoinHandle<'scope, ()>) {
+    let mut rng = StdRng::seed_from_u64(config.seed);
     let regions = find_regions(sim);
     let deposit_distance_maps = map_deposit_distances(sim);
-    let region_stats = rank_regional_factory_positions(sim, regions, deposit_distance_maps);
+    let region_stats =
+        rank_regional_factory_positions(sim, regions, deposit_distance_maps, &mut rng);
 
     let (sender, receiver) = mpsc::channel();
     let num_regions = region_stats.len();
+    let improve_config = config.clone();
+    let improve_cancel = cancel.clone();
 
     let connect_handle = scope.spawn(move || {
-        regional_connections(sim, &region_stats, sender, start);
+        regional_connections(sim, &region_stats, sender, &config, start, &cancel);
     });
     let combine_handle = scope.spawn(move || {
-        combine::combine_solutions(receiver, best_solution, num_regions);
+        let max_combinations = improve_config.max_combinations;
+        combine::combine_solutions(
+            sim,
+            receiver,
+            best_solution,
+            &improvements,
+            num_regions,
+            max_combinations,
+        );
+        // use the time left after the search for improving the best solution
+        improve(
+            sim,
+            best_solution,
+            &improvements,
+            &improve_config,
+            start,
+            &improve_cancel,
+        );
     });
 
-    (combine_handle, connect_handle)
+    (connect_handle, combine_handle)
+}
+
+/// A step of the search for regional solutions, which positions are tried in which order doesn't
+/// depend on the results
+enum Step<'a> {
+    Connect {
+        region_idx: usize,
+        product_stats: &'a ProductStats,
+        factory_stats: &'a FactoryStats,
+    },
+    AddFactories {
+        region_idx: usize,
+    },
+}
+
+/// Try the ranked factory positions of all regions in turns. Once the best ranked positions of a
+/// product are tried, further factories are built on top of the best solution of the region.
+fn schedule<'a>(region_stats: &'a [RegionStats], config: &SolverConfig) -> Vec<Step<'a>> {
+    let mut steps = Vec::new();
+    let mut product_indices = vec![0; region_stats.len()];
+    let mut factory_indices = vec![0; region_stats.len()];
+    let mut tried_positions = vec![0; region_stats.len()];
+
+    loop {
+        let mut all_done = true;
+        for (region_idx, region) in region_stats.iter().enumerate() {
+            let Some(product_stats) = region.product_stats.get(product_indices[region_idx]) else {
+                continue;
+            };
+            all_done = false;
+
+            let add_factories_now =
+                match product_stats.factory_stats.get(factory_indices[region_idx]) {
+                    Some(factory_stats) => {
+                        steps.push(Step::Connect {
+                            region_idx,
+                            product_stats,
+                            factory_stats,
+                        });
+                        factory_indices[region_idx] += 1;
+                        tried_positions[region_idx] += 1;
+                        tried_positions[region_idx] == config.factory_candidates
+                    }
+                    None => {
+                        product_indices[region_idx] += 1;
+                        factory_indices[region_idx] = 0;
+                        let tried = std::mem::take(&mut tried_positions[region_idx]);
+                        tried < config.factory_candidates
+                    }
+                };
+            if add_factories_now {
+                steps.push(Step::AddFactories { region_idx });
+            }
+        }
+
+        if all_done {
+            return steps;
+        }
+    }
 }
 
 fn regional_connections(
     sim: &Sim,
     region_stats: &[RegionStats],
     sender: mpsc::Sender<CombineMessage>,
+    config: &SolverConfig,
     start: Instant,
+    cancel: &CancelToken,
 ) {
-    'outer: for search_depth in 2..=255 {
-        let mut product_iter_indices = vec![0; region_stats.len()];
-
-        let mut region_iters = region_stats
-            .iter()
-            .map(|r| {
-                r.product_stats
-                    .iter()
-                    .map(|p| (p, p.factory_stats.iter()))
-                    .collect::<Vec<_>>()
-            })
-            .collect::<Vec<_>>();
-
-        let mut tree = ConnectionTree::new();
-        loop {
-            // TODO: try out some combinations of factories producing different products and rank those
-            // combinations
-            let mut all_done = true;
-            for (region_idx, region_iter) in region_iters.iter_mut().enumerate() {
-                let Some((product_stats, factory_stats_iter)) = region_iter.get_mut(product_iter_indices[region_idx]) else { continue };
-                all_done = false;
-
-                let Some(factory_stats) = factory_stats_iter.next() else {
-                    // TODO: try out different products
-                    product_iter_indices[region_idx] += 1;
-                    continue;
-                };
+    let steps = schedule(region_stats, config);
+    let task = Arc::new(sim.clone());
 
-                let solution = connect_deposits_and_factory(
-                    sim,
-                    &mut tree,
-                    product_stats,
-                    factory_stats,
-                    search_depth,
-                );
+    thread::scope(|scope| {
+        let mut pool = ConnectPool::new(scope, config, cancel);
+        // connections of upcoming steps queued in the pool, to keep all workers busy
+        let mut queued = VecDeque::new();
+        let mut next_queued = 0;
+        let max_queued = 2 * config.worker_threads();
 
-                if let Ok(solution) = solution {
-                    sender
-                        .send(CombineMessage::Some((region_idx, solution)))
-                        .expect("a receiver");
+        let mut iterations = 0;
+        let mut best_regional_solutions: Vec<Option<ScoredSolution>> =
+            vec![None; region_stats.len()];
+        for step in steps.iter() {
+            match *step {
+                Step::Connect { region_idx, .. } => {
+                    while queued.len() < max_queued && next_queued < steps.len() {
+                        if let Step::Connect {
+                            product_stats,
+                            factory_stats,
+                            ..
+                        } = steps[next_queued]
+                        {
+                            queued.push_back(pool.submit(
+                                task.clone(),
+                                product_stats,
+                                factory_stats,
+                            ));
+                        }
+                        next_queued += 1;
+                    }
+                    let job = queued.pop_front().expect("the connection to be queued");
+                    let solution = pool.result(job);
+                    iterations += 1;
+
+                    if let Ok(solution) = solution {
+                        let best = &mut best_regional_solutions[region_idx];
+                        if best.as_ref().is_none_or(|b| solution.run > b.run) {
+                            *best = Some(solution.clone());
+                        }
+                        sender
+                            .send(CombineMessage::Some((region_idx, solution)))
+                            .expect("a receiver");
+                    }
+
+                    if config.is_exhausted(iterations, start, sim.time) || cancel.is_cancelled() {
+                        break;
+                    }
+                }
+                Step::AddFactories { region_idx } => {
+                    let exhausted = add_factories(
+                        sim,
+                        region_idx,
+                        &region_stats[region_idx],
+                        &mut best_regional_solutions[region_idx],
+                        &mut pool,
+                        &mut iterations,
+                        &sender,
+                        config,
+                        start,
+                        cancel,
+                    );
+                    if exhausted {
+                        break;
+                    }
                 }
             }
+        }
+    });
+
+    sender.send(CombineMessage::Done).expect("a receiver");
+}
+
+/// Repeatedly add the factory that improves the best solution of a region the most, until no
+/// factory improves it or `max_factories` is reached. Returns whether the budget is exhausted.
+#[allow(clippy::too_many_arguments)]
+fn add_factories<'a>(
+    sim: &Sim,
+    region_idx: usize,
+    region_stats: &'a RegionStats,
+    best: &mut Option<ScoredSolution>,
+    pool: &mut ConnectPool<'a>,
+    iterations: &mut u32,
+    sender: &mpsc::Sender<CombineMessage>,
+    config: &SolverConfig,
+    start: Instant,
+    cancel: &CancelToken,
+) -> bool {
+    let Some(best) = best else { return false };
+
+    let mut num_factories = best
+        .sim
+        .buildings
+        .iter()
+        .filter(|(_, b)| matches!(b, Building::Factory(_)))
+        .count() as u32;
+    while num_factories < config.max_factories {
+        // all candidates are searched in parallel
+        let best_sim = Arc::new(best.sim.clone());
+        let mut jobs = Vec::new();
+        for product_stats in region_stats.product_stats.iter() {
+            let candidates = product_stats
+                .factory_stats
+                .iter()
+                .filter(|f| factory_fits(&best.sim, f.pos))
+                .take(config.factory_candidates as usize);
+            for factory_stats in candidates {
+                jobs.push(pool.submit(best_sim.clone(), product_stats, factory_stats));
+            }
+        }
+
+        let mut improved: Option<ScoredSolution> = None;
+        for job in jobs {
+            let solution = pool.result(job);
+            *iterations += 1;
 
-            let now = Instant::now();
-            if (now - start).as_secs_f32() > sim.time - 0.1 {
-                break 'outer;
+            if let Ok(solution) = solution {
+                let current = improved.as_ref().unwrap_or(best);
+                if solution.run > current.run {
+                    improved = Some(solution);
+                }
             }
 
-            if all_done {
-                break;
+            if config.is_exhausted(*iterations, start, sim.time) || cancel.is_cancelled() {
+                if let Some(solution) = improved {
+                    sender
+                        .send(CombineMessage::Some((region_idx, solution)))
+                        .expect("a receiver");
+                }
+                return true;
             }
         }
+
+        let Some(solution) = improved else { break };
+        sender
+            .send(CombineMessage::Some((region_idx, solution.clone())))
+            .expect("a receiver");
+        *best = solution;
+        num_factories += 1;
     }
 
-    sender.send(CombineMessage::Done).expect("a receiver");
+    false
 }
@@ -0,0 +1,145 @@
+//! Worker threads that connect deposits to factory positions in parallel.
+//!
+//! Every job is the search of a single factory position, see [`connect_deposits_and_factory`].
+//! Jobs are numbered in the order they're submitted and the solver waits for their results by
+//! number, so it sees the same results in the same order no matter how many workers there are and
+//! which of them finishes first. This keeps runs with an iteration budget reproducible.
+
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use sim::{Evaluator, Sim};
+
+use crate::{
+    connect_deposits_and_factory, CancelToken, ConnectionTree, Error, FactoryStats, ProductStats,
+    ScoredSolution, SolverConfig,
+};
+
+struct Job<'a> {
+    id: u64,
+    sim: Arc<Sim>,
+    product_stats: &'a ProductStats,
+    factory_stats: &'a FactoryStats,
+}
+
+type JobResult = (u64, crate::Result<ScoredSolution>);
+
+pub(crate) struct ConnectPool<'a> {
+    jobs: Option<mpsc::Sender<Job<'a>>>,
+    results: mpsc::Receiver<JobResult>,
+    /// Results of jobs that finished before the ones the solver waited for
+    finished: HashMap<u64, crate::Result<ScoredSolution>>,
+    /// Cancelled once the pool is dropped, so that running jobs stop and queued ones are skipped
+    stopped: CancelToken,
+    next_id: u64,
+}
+
+impl<'a> ConnectPool<'a> {
+    /// Spawn the workers on the scope, they run until the pool is dropped. Jobs stop early once
+    /// `cancel` is cancelled.
+    pub(crate) fn new<'scope>(
+        scope: &'scope thread::Scope<'scope, '_>,
+        config: &'a SolverConfig,
+        cancel: &CancelToken,
+    ) -> Self
+    where
+        'a: 'scope,
+    {
+        let (job_sender, job_receiver) = mpsc::channel::<Job<'a>>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let stopped = cancel.child();
+
+        for _ in 0..config.worker_threads() {
+            let job_receiver = job_receiver.clone();
+            let result_sender = result_sender.clone();
+            let stopped = stopped.clone();
+            scope.spawn(move || {
+                let mut tree = ConnectionTree::new();
+                let mut evaluator = Evaluator::new();
+                loop {
+                    let job = job_receiver.lock().expect("lock not to be poisoned").recv();
+                    let Ok(job) = job else { break };
+
+                    // skipped jobs still get a result, the solver may be waiting for it
+                    let res = match stopped.is_cancelled() {
+                        true => Err(Error::NoSolution),
+                        // so do jobs whose search panics, unless panics abort the process
+                        false => panic::catch_unwind(AssertUnwindSafe(|| {
+                            connect_deposits_and_factory(
+                                &job.sim,
+                                &mut tree,
+                                job.product_stats,
+                                job.factory_stats,
+                                config,
+                                &mut evaluator,
+                                &stopped,
+                            )
+                        }))
+                        .unwrap_or_else(|_| {
+                            // the search may have left them half updated
+                            tree = ConnectionTree::new();
+                            evaluator = Evaluator::new();
+                            Err(Error::NoSolution)
+                        }),
+                    };
+                    if result_sender.send((job.id, res)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        Self {
+            jobs: Some(job_sender),
+            results,
+            finished: HashMap::new(),
+            stopped,
+            next_id: 0,
+        }
+    }
+
+    /// Queue the search of a factory position, returns the number of the job
+    pub(crate) fn submit(
+        &mut self,
+        sim: Arc<Sim>,
+        product_stats: &'a ProductStats,
+        factory_stats: &'a FactoryStats,
+    ) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        let job = Job {
+            id,
+            sim,
+            product_stats,
+            factory_stats,
+        };
+        self.jobs
+            .as_ref()
+            .expect("pool not to be stopped")
+            .send(job)
+            .expect("workers to be running");
+        id
+    }
+
+    /// Wait for the result of a job
+    pub(crate) fn result(&mut self, id: u64) -> crate::Result<ScoredSolution> {
+        loop {
+            if let Some(res) = self.finished.remove(&id) {
+                return res;
+            }
+            let (finished_id, res) = self.results.recv().expect("workers to be running");
+            self.finished.insert(finished_id, res);
+        }
+    }
+}
+
+impl Drop for ConnectPool<'_> {
+    fn drop(&mut self) {
+        self.stopped.cancel();
+        // the workers stop once the queue is closed
+        self.jobs = None;
+    }
+}
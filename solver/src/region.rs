@@ -1,3 +1,5 @@
+use std::collections::VecDeque;
+
 use sim::{pos, Building, Id, Pos, Sim, MAX_BOARD_SIZE};
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
@@ -122,83 +124,90 @@ impl Visited {
 pub fn find_regions(sim: &Sim) -> Regions {
     let mut visited = Visited::new(sim.board.width, sim.board.height);
     let mut regions = Regions::default();
-    let mut position = pos(0, 0);
+    let mut queue = VecDeque::new();
 
     // TODO: consider filtering out regions that have not enough space to place buildings
-    loop {
-        regions.new_region();
-        find_region(sim, &mut visited, &mut regions, position);
+    for y in 0..visited.height {
+        for x in 0..visited.width {
+            if visited[(x, y)] {
+                continue;
+            }
 
-        if regions.get(regions.len() - 1).cells.is_empty() {
-            regions.pop();
-        }
+            regions.new_region();
+            find_region(sim, &mut visited, &mut regions, &mut queue, pos(x, y));
 
-        let mut all_visited = true;
-        'outer: for y in 0..visited.height {
-            for x in 0..visited.width {
-                if !visited[(x, y)] {
-                    position = pos(x, y);
-                    all_visited = false;
-                    break 'outer;
-                }
+            if regions.get(regions.len() - 1).cells.is_empty() {
+                regions.pop();
             }
         }
-
-        if all_visited {
-            break;
-        }
     }
 
     regions
 }
 
-fn find_region(sim: &Sim, visited: &mut Visited, regions: &mut Regions, pos: Pos) {
-    if pos.x < 0 || pos.x >= visited.width || pos.y < 0 || pos.y >= visited.height {
-        return;
-    }
+/// Flood fill the empty cells connected to `start`, collecting the deposits bordering them
+fn find_region(
+    sim: &Sim,
+    visited: &mut Visited,
+    regions: &mut Regions,
+    queue: &mut VecDeque<Pos>,
+    start: Pos,
+) {
+    queue.clear();
+    queue.push_back(start);
+
+    while let Some(pos) = queue.pop_front() {
+        if pos.x < 0 || pos.x >= visited.width || pos.y < 0 || pos.y >= visited.height {
+            continue;
+        }
 
-    let region = regions.get(regions.len() - 1);
-    if let Some(c) = sim.board[pos] {
-        let building = &sim.buildings[c.id];
-        match &building {
-            Building::Deposit(deposit) => {
-                if !region.deposits.contains(&c.id) {
-                    regions.deposits.push(c.id);
-                }
+        if let Some(c) = sim.board[pos] {
+            let building = &sim.buildings[c.id];
+            match &building {
+                Building::Deposit(deposit) => {
+                    let region = regions.get(regions.len() - 1);
+                    if !region.deposits.contains(&c.id) {
+                        regions.deposits.push(c.id);
+                    }
 
-                for y in 0..deposit.height as i8 {
-                    for x in 0..deposit.width as i8 {
-                        visited[deposit.pos + (x, y)] = true;
+                    for y in 0..deposit.height as i8 {
+                        for x in 0..deposit.width as i8 {
+                            visited[deposit.pos + (x, y)] = true;
+                        }
                     }
                 }
-
-                return;
-            }
-            Building::Obstacle(obstacle) => {
-                for y in 0..obstacle.height as i8 {
-                    for x in 0..obstacle.width as i8 {
-                        visited[obstacle.pos + (x, y)] = true;
+                Building::Obstacle(obstacle) => {
+                    for y in 0..obstacle.height as i8 {
+                        for x in 0..obstacle.width as i8 {
+                            visited[obstacle.pos + (x, y)] = true;
+                        }
                     }
                 }
-
-                return;
+                // prebuilt paths can be crossed and merged into, they connect the cells around
+                // them without being part of the region
+                Building::Mine(_) | Building::Conveyor(_) | Building::Combiner(_) => {
+                    if !visited[pos] {
+                        visited[pos] = true;
+                        for offset in [(0, -1), (-1, 0), (0, 1), (1, 0)] {
+                            queue.push_back(pos + offset);
+                        }
+                    }
+                }
+                // prebuilt factories block the cells they cover, like obstacles
+                Building::Factory(_) => visited[pos] = true,
             }
-            Building::Mine(_)
-            | Building::Conveyor(_)
-            | Building::Combiner(_)
-            | Building::Factory(_) => todo!(),
+            continue;
         }
-    } else if !region.cells.contains(&pos) {
+
+        if visited[pos] {
+            continue;
+        }
+        visited[pos] = true;
         regions.cells.push(pos);
-    }
 
-    if visited[pos] {
-        return;
+        queue.push_back(pos + (0, -1));
+        queue.push_back(pos + (-1, 0));
+        queue.push_back(pos + (0, 1));
+        queue.push_back(pos + (1, 0));
     }
-    visited[pos] = true;
-
-    find_region(sim, visited, regions, pos + (0, -1));
-    find_region(sim, visited, regions, pos + (-1, 0));
-    find_region(sim, visited, regions, pos + (0, 1));
-    find_region(sim, visited, regions, pos + (1, 0));
 }
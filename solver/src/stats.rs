@@ -1,8 +1,10 @@
 use std::collections::HashMap;
 
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
 use sim::{Pos, ResourceType, Id, ProductType, Sim, Resources, Building, FACTORY_SIZE};
 
-use crate::{Regions, DistanceMap};
+use crate::{best_product_mix, border_cells, border_distance, free_neighbors, min_connectors, Regions, DistanceMap};
 
 
 pub struct RegionStats {
@@ -12,6 +14,8 @@ pub struct RegionStats {
 pub struct ProductStats {
     pub product_type: ProductType,
     pub max_points: u32,
+    /// Points of this product in the [`ResourcePlan`] of the region
+    pub planned_points: u32,
     pub deposit_stats: Vec<DepositStats>,
     pub factory_stats: Vec<FactoryStats>,
 }
@@ -19,12 +23,17 @@ pub struct ProductStats {
 pub struct DepositStats {
     pub id: Id,
     pub resource_type: ResourceType,
+    /// Resources of the deposit reserved for the product
     pub resources: u16,
+    /// Number of mines that fit next to the deposit
+    pub mines: u32,
     pub weight: f32,
 }
 
 pub struct FactoryStats {
     pub pos: Pos,
+    /// Whether a factory of the product is already built at the position
+    pub prebuilt: bool,
     pub score: Score,
     /// indices into deposit_stats
     pub deposits_in_reach: Vec<DepositIdx>,
@@ -52,6 +61,7 @@ pub fn rank_regional_factory_positions(
     sim: &Sim,
     regions: Regions,
     deposit_distance_maps: HashMap<Id, DistanceMap>,
+    rng: &mut StdRng,
 ) -> Vec<RegionStats> {
     regions.iter().filter_map(|region| {
         let mut available_resources = Resources::default();
@@ -60,6 +70,8 @@ pub fn rank_regional_factory_positions(
             available_resources.values[deposit.resource_type as usize] += deposit.resources();
         }
 
+        let plan = plan_resources(sim, region.deposits);
+
         let mut product_stats = sim.products.iter()
             .enumerate()
             .filter_map(|(i, product)| {
@@ -81,9 +93,12 @@ pub fn rank_regional_factory_positions(
                 };
 
                 let product_type = ProductType::try_from(i as u8).unwrap();
+                let planned = plan.get(product_type);
+                let planned_points = planned.map_or(0, |p| p.points);
 
                 // calculate a weight for a deposit and filter out ones that don't provide any
-                // resources needed for the current product
+                // resources needed for the current product, or whose resources are planned for
+                // other products
                 let deposit_stats = region
                     .deposits
                     .iter()
@@ -96,65 +111,76 @@ pub fn rank_regional_factory_positions(
                             return None;
                         }
 
-                        // TODO: possibly factor in if there are other deposits of the same resource
-                        // type in the region
-                        let resources = deposit.resources();
+                        let resources = match planned {
+                            Some(p) => p.deposit_resources(id),
+                            None => deposit.resources(),
+                        };
+                        if resources == 0 {
+                            return None;
+                        }
                         let weight = needed_resources as f32 * resources as f32;
+                        let mines = free_neighbors(sim, deposit.pos, deposit.width as i8, deposit.height as i8);
 
-                        Some(DepositStats { id, resource_type, resources, weight })
+                        Some(DepositStats { id, resource_type, resources, mines, weight })
                     })
                     .collect::<Vec<_>>();
 
-                let mut factory_stats = region
-                    .cells
+                let mut product_resources = Resources::default();
+                for ds in deposit_stats.iter() {
+                    product_resources[ds.resource_type] += ds.resources;
+                }
+                if !product_resources.has_at_least(&product.resources) {
+                    return None;
+                }
+
+                // prebuilt factories of the product next to the region are candidates as well
+                let prebuilt = sim.buildings.iter().filter_map(|(_, b)| match b {
+                    Building::Factory(f) if f.product_type == product_type => Some(f.pos),
+                    _ => None,
+                })
+                .filter(|&p| {
+                    border_cells(p + (-1, -1), FACTORY_SIZE + 2, FACTORY_SIZE + 2)
+                        .any(|c| region.cells.contains(&c))
+                })
+                .collect::<Vec<_>>();
+
+                let mut factory_stats = prebuilt
                     .iter()
+                    .chain(region.cells.iter())
                     .filter_map(|&factory_pos| {
                         // check if a factory could even be placed here
-                        for y in 0..FACTORY_SIZE {
-                            for x in 0..FACTORY_SIZE {
-                                let p = factory_pos + (x, y);
-                                // out of bounds
-                                let cell = sim.board.get(p)?;
-                                // cell is non-empty
-                                if cell.is_some() {
-                                    return None;
-                                }
-                            }
+                        let prebuilt = existing_factory(sim, factory_pos, product_type).is_some();
+                        if !prebuilt && !factory_fits(sim, factory_pos) {
+                            return None;
                         }
 
                         let mut max = WeightedDist { dist: 0.0, weighted: 0.0 };
                         let mut min = WeightedDist {dist: f32::MAX, weighted:f32::MAX};
                         let mut sum = WeightedDist { dist: 0.0, weighted: 0.0 };
-                        let mut resources_in_reach = available_resources;
+                        let mut resources_in_reach = product_resources;
                         let mut deposits_in_reach = Vec::with_capacity(region.deposits.len());
                         for (idx, ds) in deposit_stats.iter().enumerate() {
                             let map = &deposit_distance_maps[&ds.id];
-                            // find the distance from the outer border of the factory
-                            let mut dist = u16::MAX;
-                            for i in 0..FACTORY_SIZE {
-                                let pos = factory_pos + (i, 0);
-                                if let Some(Some(d)) = map.get(pos) {
-                                    dist = dist.min(d);
-                                }
-                            }
-                            for i in 1..FACTORY_SIZE - 1 {
-                                let pos = factory_pos + (0, i);
-                                if let Some(Some(d)) = map.get(pos) {
-                                    dist = dist.min(d);
-                                }
-                                let pos = factory_pos + (FACTORY_SIZE - 1, i);
-                                if let Some(Some(d)) = map.get(pos) {
-                                    dist = dist.min(d);
-                                }
-                            }
-                            for i in 0..FACTORY_SIZE {
-                                let pos = factory_pos + (i, FACTORY_SIZE - 1);
-                                if let Some(Some(d)) = map.get(pos) {
-                                    dist = dist.min(d);
-                                }
+                            // find the distance from the outer border of the factory, the
+                            // cells of a prebuilt factory are occupied so the cells around it
+                            // are one step further away
+                            let dist = match prebuilt {
+                                false => border_distance(map, factory_pos, FACTORY_SIZE, FACTORY_SIZE),
+                                true => border_distance(map, factory_pos + (-1, -1), FACTORY_SIZE + 2, FACTORY_SIZE + 2)
+                                    .map(|d| d + 1),
                             }
+                            .unwrap_or(u16::MAX);
 
                             let deposit_idx = DepositIdx { idx };
+                            // resources that can be delivered to the factory by the turn limit,
+                            // allowing one more building than a straight path for going around
+                            // corners
+                            let deliverable = sim::delivered_resources(
+                                ds.resources as u32,
+                                sim::MINE_RATE * ds.mines,
+                                sim::arrival_turn(min_connectors(dist) + 1),
+                                sim.turns,
+                            );
                             let dist = dist as f32;
                             let weighted = ds.weight / (dist + 1.0);
 
@@ -168,11 +194,11 @@ pub fn rank_regional_factory_positions(
 
                             if dist == 0.0 {
                                 return None;
-                            } else if (dist as u32 / 4) + 2 < sim.turns {
+                            }
+                            if deliverable > 0 {
                                 deposits_in_reach.push(deposit_idx);
-                            } else {
-                                resources_in_reach[ds.resource_type] -= ds.resources;
                             }
+                            resources_in_reach[ds.resource_type] -= ds.resources - deliverable as u16;
                         }
 
                         // Filter out factory positions that can't reach all necessary resources
@@ -193,7 +219,7 @@ pub fn rank_regional_factory_positions(
                             max_products: 1.0 / (max_products + 2.0).ln(),
                         };
 
-                        Some(FactoryStats { pos: factory_pos, score, deposits_in_reach })
+                        Some(FactoryStats { pos: factory_pos, prebuilt, score, deposits_in_reach })
                     })
                     .collect::<Vec<_>>();
 
@@ -223,20 +249,125 @@ pub fn rank_regional_factory_positions(
                     d.score.max_products = (d.score.max_products - min_score.max_products) / (max_score.max_products - min_score.max_products);
                 });
 
-                // rank by score
+                // rank by score, equally scored positions are ordered randomly
+                factory_stats.shuffle(rng);
                 factory_stats.sort_by(|f1, f2| {
                     let score1 = f1.score.dist + f1.score.middle + f1.score.weighted + f1.score.max_products;
                     let score2 = f2.score.dist + f2.score.middle + f2.score.weighted + f2.score.max_products;
                     score2.total_cmp(&score1)
                 });
+                // building on prebuilt factories is tried first
+                factory_stats.sort_by_key(|f| !f.prebuilt);
 
-                Some(ProductStats { product_type, max_points, deposit_stats, factory_stats })
+                Some(ProductStats { product_type, max_points, planned_points, deposit_stats, factory_stats })
             }).collect::<Vec<_>>();
 
-        product_stats.sort_by(|p1, p2| p2.max_points.cmp(&p1.max_points));
+        product_stats.sort_by_key(|p| std::cmp::Reverse((p.planned_points, p.max_points)));
 
         (!product_stats.is_empty()).then_some(RegionStats { product_stats })
     })
     .collect()
 }
 
+
+/// Whether all cells of a factory at `pos` are in bounds and empty
+pub(crate) fn factory_fits(sim: &Sim, pos: Pos) -> bool {
+    (0..FACTORY_SIZE).all(|y| {
+        (0..FACTORY_SIZE).all(|x| matches!(sim.board.get(pos + (x, y)), Some(None)))
+    })
+}
+
+/// The prebuilt factory of the product at `pos`, that paths can be connected to
+pub(crate) fn existing_factory(sim: &Sim, pos: Pos, product_type: ProductType) -> Option<Id> {
+    let cell = sim.board.get(pos).flatten()?;
+    match &sim.buildings[cell.id] {
+        Building::Factory(f) if f.pos == pos && f.product_type == product_type => Some(cell.id),
+        _ => None,
+    }
+}
+
+/// How the resources of a region are split between products to make the most points
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResourcePlan {
+    /// Products that should be made, ordered by their points
+    pub products: Vec<PlannedProduct>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlannedProduct {
+    pub product_type: ProductType,
+    pub count: u32,
+    pub points: u32,
+    /// Resources of every deposit reserved for this product
+    pub deposits: Vec<(Id, u16)>,
+}
+
+impl ResourcePlan {
+    pub fn get(&self, product_type: ProductType) -> Option<&PlannedProduct> {
+        self.products
+            .iter()
+            .find(|p| p.product_type == product_type)
+    }
+}
+
+impl PlannedProduct {
+    pub fn deposit_resources(&self, id: Id) -> u16 {
+        self.deposits
+            .iter()
+            .find(|(i, _)| *i == id)
+            .map_or(0, |(_, r)| *r)
+    }
+}
+
+/// Find the product mix that makes the most points out of the resources of `deposits`, and split
+/// the resources of every deposit between those products. Products that make more points are
+/// assigned the biggest deposits first, so products draw from as few deposits as possible.
+pub fn plan_resources(sim: &Sim, deposits: &[Id]) -> ResourcePlan {
+    let mut available = Resources::default();
+    let mut remaining = Vec::with_capacity(deposits.len());
+    for &id in deposits.iter() {
+        let Building::Deposit(deposit) = &sim.buildings[id] else {
+            continue;
+        };
+        let resources = &mut available[deposit.resource_type];
+        *resources = resources.saturating_add(deposit.resources());
+        remaining.push((id, deposit.resource_type, deposit.resources()));
+    }
+    remaining.sort_by_key(|&(id, _, resources)| (std::cmp::Reverse(resources), id.0));
+
+    let mix = best_product_mix(&sim.products, available);
+
+    let mut products = mix
+        .counts
+        .iter()
+        .enumerate()
+        .filter(|(_, &count)| count > 0)
+        .map(|(i, &count)| {
+            let product_type = ProductType::try_from(i as u8).unwrap();
+            let points = sim.products[product_type].points * count;
+            PlannedProduct {
+                product_type,
+                count,
+                points,
+                deposits: Vec::new(),
+            }
+        })
+        .collect::<Vec<_>>();
+    products.sort_by_key(|p| std::cmp::Reverse(p.points));
+
+    for planned in products.iter_mut() {
+        let needed = sim.products[planned.product_type].resources;
+        let mut demand = needed.values.map(|r| r as u32 * planned.count);
+        for (id, resource_type, resources) in remaining.iter_mut() {
+            let demand = &mut demand[*resource_type as usize];
+            let share = (*resources as u32).min(*demand) as u16;
+            if share > 0 {
+                *resources -= share;
+                *demand -= share as u32;
+                planned.deposits.push((*id, share));
+            }
+        }
+    }
+
+    ResourcePlan { products }
+}
@@ -1,8 +1,21 @@
 use std::cmp::Ordering;
+use std::sync::Arc;
+use std::time::Instant;
 
-use sim::{dto, place_building, pos, Board, Building, Id, Obstacle, Products, Sim};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 
-use crate::{find_regions, Regions};
+use sim::{
+    dto, place_building, pos, Board, Building, Conveyor, Deposit, Id, Obstacle, Product,
+    ProductType, Products, ResourceType, Resources, Rotation, Sim,
+};
+
+use crate::pool::ConnectPool;
+use crate::{
+    find_regions, map_deposit_distances, map_distances, max_product_points, plan_resources,
+    rank_regional_factory_positions, score_bound, Budget, CancelToken, Error, Regions,
+    SolverConfig,
+};
 
 const TURNS: u32 = 100;
 const TIME: f32 = 100.0;
@@ -47,6 +60,20 @@ fn find_two_regions() {
     assert_eq!(regions, expected,);
 }
 
+#[test]
+fn prebuilt_conveyors_dont_split_regions() {
+    let mut sim = Sim::new(Products::default(), Board::new(6, 6), TURNS, TIME);
+    for y in [1, 4] {
+        let conveyor = Conveyor::new((3, y), Rotation::Down, false);
+        place_building(&mut sim, Building::Conveyor(conveyor)).unwrap();
+    }
+
+    // paths can cross the conveyors, but their cells aren't free
+    let regions = find_regions(&sim);
+    assert_eq!(regions.len(), 1);
+    assert_eq!(regions.get(0).cells.len(), 30);
+}
+
 #[test]
 fn find_regions_of_task_002() {
     let input = std::fs::read_to_string("../tasks/002/task.json").unwrap();
@@ -78,3 +105,388 @@ fn find_regions_of_task_002() {
 
     assert_eq!(regions, expected);
 }
+
+fn read_task(path: &str) -> dto::Task {
+    let input = std::fs::read_to_string(path).unwrap();
+    serde_json::from_str(&input).unwrap()
+}
+
+fn solve_task(path: &str, config: SolverConfig) -> dto::Solution {
+    crate::solve(&read_task(path), config).unwrap()
+}
+
+#[test]
+fn solver_runs_with_iteration_budget_are_reproducible() {
+    let config = SolverConfig {
+        seed: 7,
+        budget: Budget::Iterations(6),
+        ..SolverConfig::default()
+    };
+    let first = solve_task("../tasks/002/task.json", config.clone());
+    let second = solve_task("../tasks/002/task.json", config);
+
+    assert_eq!(first, second);
+}
+
+#[test]
+fn solve_partially_built_task() {
+    let config = SolverConfig {
+        budget: Budget::Iterations(6),
+        ..SolverConfig::default()
+    };
+    let mut task = read_task("../tasks/002/task.json");
+    let solution = crate::solve(&task, config.clone()).unwrap();
+
+    // ship the factory and the first path of the solution with the task
+    let prebuilt = solution.objects().iter().take(4).map(|o| dto::TaskObject {
+        kind: o.kind.clone(),
+        subtype: o.subtype,
+        x: o.x,
+        y: o.y,
+        width: 0,
+        height: 0,
+    });
+    task.objects.extend(prebuilt);
+    let sim = Sim::try_from(&task).unwrap();
+    let prebuilt_points = sim::run(&sim).points;
+    let bound = score_bound(&sim).reachable_points;
+    assert!(prebuilt_points <= bound);
+
+    // no other factory fits next to the deposit, new paths lead into the prebuilt one
+    let solution = crate::solve(&task, config).unwrap();
+    let mut sim = Sim::try_from(&task).unwrap();
+    solution.place(&mut sim).unwrap();
+    assert!(solution
+        .objects()
+        .iter()
+        .all(|o| o.kind != dto::ObjectKind::Factory));
+    let points = sim::run(&sim).points;
+    assert!(points > prebuilt_points);
+    assert!(points <= bound);
+}
+
+#[test]
+fn improvements_end_with_the_best_solution() {
+    let task = read_task("../tasks/001/task.json");
+    let config = SolverConfig {
+        budget: Budget::Iterations(6),
+        ..SolverConfig::default()
+    };
+
+    let mut improvements = Vec::new();
+    let cancel = CancelToken::new();
+    let best = crate::solve_with(&task, config, &cancel, |s| improvements.push(s.clone())).unwrap();
+
+    let points = improvements
+        .iter()
+        .map(|solution| {
+            let mut sim = Sim::try_from(&task).unwrap();
+            solution.place(&mut sim).unwrap();
+            sim::run(&sim).points
+        })
+        .collect::<Vec<_>>();
+    assert!(points.windows(2).all(|w| w[0] <= w[1]));
+    assert_eq!(improvements.last(), Some(&best));
+}
+
+#[test]
+fn cancelled_solver_stops_right_away() {
+    let task = read_task("../tasks/001/task.json");
+
+    let cancel = CancelToken::new();
+    let start = Instant::now();
+    cancel.cancel();
+    let solution = crate::solve_with(&task, SolverConfig::default(), &cancel, |_| ());
+
+    // the time limit of the task is minutes away
+    assert!(solution.is_ok());
+    assert!(start.elapsed().as_secs_f32() < task.time / 2.0);
+}
+
+#[test]
+fn cancelling_a_child_token_leaves_the_parent_alone() {
+    let parent = CancelToken::new();
+    let child = parent.child();
+    let grandchild = child.child();
+
+    grandchild.cancel();
+    assert!(!child.is_cancelled());
+    child.cancel();
+    assert!(!parent.is_cancelled());
+
+    let child = parent.child();
+    parent.cancel();
+    assert!(child.is_cancelled());
+}
+
+#[test]
+fn solver_doesnt_cancel_a_shared_token() {
+    let task = read_task("../tasks/002/task.json");
+    let config = SolverConfig {
+        budget: Budget::Iterations(6),
+        ..SolverConfig::default()
+    };
+
+    let cancel = CancelToken::new();
+    let first = crate::solve_with(&task, config.clone(), &cancel, |_| ()).unwrap();
+    assert!(!cancel.is_cancelled());
+    let second = crate::solve_with(&task, config, &cancel, |_| ()).unwrap();
+
+    assert!(!first.objects().is_empty());
+    assert_eq!(first, second);
+}
+
+#[test]
+fn pool_answers_jobs_whose_search_panics() {
+    let sim = Sim::try_from(&read_task("../tasks/002/task.json")).unwrap();
+    let mut rng = StdRng::seed_from_u64(0);
+    let deposit_distance_maps = map_deposit_distances(&sim);
+    let region_stats =
+        rank_regional_factory_positions(&sim, find_regions(&sim), deposit_distance_maps, &mut rng);
+    let product_stats = &region_stats[0].product_stats[0];
+    let factory_stats = &product_stats.factory_stats[0];
+    // the search doesn't expect the deposit to be gone
+    let mut broken = sim.clone();
+    sim::remove_building(&mut broken, product_stats.deposit_stats[0].id);
+
+    let config = SolverConfig {
+        threads: 1,
+        ..SolverConfig::default()
+    };
+    let cancel = CancelToken::new();
+    std::thread::scope(|s| {
+        let mut pool = ConnectPool::new(s, &config, &cancel);
+        let job = pool.submit(Arc::new(broken), product_stats, factory_stats);
+        assert!(matches!(pool.result(job), Err(Error::NoSolution)));
+
+        // the worker carries on with the next job
+        let job = pool.submit(Arc::new(sim.clone()), product_stats, factory_stats);
+        assert!(pool.result(job).is_ok());
+    });
+}
+
+#[test]
+fn invalid_tasks_are_reported() {
+    let mut task = read_task("../tasks/001/task.json");
+    task.objects[0].x = task.width;
+
+    let res = crate::solve(&task, SolverConfig::default());
+    assert!(matches!(
+        res,
+        Err(Error::Task(dto::TaskError::Object(dto::ObjectError {
+            index: 0,
+            error: sim::Error::OutOfBounds(_),
+        })))
+    ));
+}
+
+#[test]
+fn solutions_dont_depend_on_the_number_of_threads() {
+    let solutions = [1, 3].map(|threads| {
+        let config = SolverConfig {
+            budget: Budget::Iterations(12),
+            threads,
+            ..SolverConfig::default()
+        };
+        solve_task("../tasks/003/task.json", config)
+    });
+
+    assert_eq!(solutions[0], solutions[1]);
+}
+
+#[test]
+fn partial_config_uses_defaults() {
+    let config: SolverConfig =
+        serde_json::from_str(r#"{ "budget": { "iterations": 50 }, "max_combinations": 10 }"#)
+            .unwrap();
+
+    let expected = SolverConfig {
+        budget: Budget::Iterations(50),
+        max_combinations: 10,
+        ..SolverConfig::default()
+    };
+    assert_eq!(config, expected);
+    assert_eq!(config.validate(), Ok(()));
+}
+
+#[test]
+fn invalid_route_nodes() {
+    let config = SolverConfig {
+        max_route_nodes: 0,
+        ..SolverConfig::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn bound_integer_product_mix() {
+    let mut products = Products::default();
+    products[ProductType::Type0] = Product::new(Resources::new([3, 0, 0, 0, 0, 0, 0, 0]), 5);
+    products[ProductType::Type1] = Product::new(Resources::new([2, 1, 0, 0, 0, 0, 0, 0]), 3);
+    let available = Resources::new([10, 3, 0, 0, 0, 0, 0, 0]);
+
+    // the LP relaxation would be 16.6 with 3.33x Type0, 3x Type0 + 0x Type1 gives 15, but
+    // 2x Type0 + 2x Type1 gives 16
+    assert_eq!(max_product_points(&products, available), 16);
+}
+
+#[test]
+fn bound_limited_by_distance_and_turns() {
+    let mut products = Products::default();
+    products[ProductType::Type0] = Product::new(Resources::new([3, 0, 0, 0, 0, 0, 0, 0]), 5);
+    let mut sim = Sim::new(products, Board::new(30, 5), 10, TIME);
+    let deposit = Deposit::new(pos(0, 0), 5, 5, ResourceType::Type0);
+    place_building(&mut sim, Building::Deposit(deposit)).unwrap();
+    // leave a corridor of one cell, so there is room for a single mine and factories are at least
+    // 10 cells away
+    place_building(
+        &mut sim,
+        Building::Obstacle(Obstacle::new(pos(5, 0), 10, 4)),
+    )
+    .unwrap();
+
+    let bound = score_bound(&sim);
+
+    // 125 resources make 41 products
+    assert_eq!(bound.max_points, 205);
+    // 3 buildings are needed to cover the distance, leaving 6 turns for a single mine
+    assert_eq!(bound.reachable_points, 30);
+}
+
+#[test]
+fn bound_of_every_task() {
+    for i in 1..=10 {
+        let input = std::fs::read_to_string(format!("../tasks/{i:03}/task.json")).unwrap();
+        let task: dto::Task = serde_json::from_str(&input).unwrap();
+        let sim = Sim::try_from(&task).unwrap();
+
+        let bound = score_bound(&sim);
+        assert!(
+            bound.reachable_points <= bound.max_points,
+            "task {i:03}: {bound:?}"
+        );
+    }
+}
+
+/// A 100x100 board with walls on every odd row, leaving a gap at alternating ends, and a deposit in
+/// the top left corner
+fn max_size_maze() -> Sim {
+    let mut sim = Sim::new(Products::default(), Board::new(100, 100), TURNS, TIME);
+    let deposit = Deposit::new(pos(0, 0), 1, 1, ResourceType::Type0);
+    place_building(&mut sim, Building::Deposit(deposit)).unwrap();
+    for k in 0..49 {
+        let wall = Obstacle::new(pos((k % 2) as i8, 2 * k as i8 + 1), 99, 1);
+        place_building(&mut sim, Building::Obstacle(wall)).unwrap();
+    }
+    sim
+}
+
+#[test]
+fn find_regions_on_max_size_boards() {
+    let sim = Sim::new(Products::default(), Board::new(100, 100), TURNS, TIME);
+    let regions = find_regions(&sim);
+    assert_eq!(regions.len(), 1);
+    assert_eq!(regions.get(0).cells.len(), 100 * 100);
+
+    let sim = max_size_maze();
+    let regions = find_regions(&sim);
+    assert_eq!(regions.len(), 1);
+    assert_eq!(regions.get(0).deposits, &[Id(0)]);
+    assert_eq!(regions.get(0).cells.len(), 100 * 100 - 49 * 99 - 1);
+}
+
+#[test]
+fn map_distances_on_max_size_maze() {
+    let sim = max_size_maze();
+    let map = map_distances(&sim, pos(0, 0), 1, 1);
+
+    assert_eq!(map[pos(1, 0)], Some(0));
+    assert_eq!(map[pos(0, 1)], None);
+    // every lane takes 99 steps and another 2 to get through the gap in the wall
+    assert_eq!(map[pos(99, 0)], Some(98));
+    assert_eq!(map[pos(0, 2)], Some(98 + 101));
+    assert_eq!(map[pos(0, 98)], Some(98 + 101 * 49));
+    assert_eq!(map[pos(0, 99)], Some(98 + 101 * 49 + 1));
+}
+
+#[test]
+fn build_a_factory_per_product_in_one_region() {
+    let mut products = Products::default();
+    products[ProductType::Type0] = Product::new(Resources::new([3, 0, 0, 0, 0, 0, 0, 0]), 10);
+    products[ProductType::Type1] = Product::new(Resources::new([0, 3, 0, 0, 0, 0, 0, 0]), 10);
+    let mut sim = Sim::new(products, Board::new(30, 12), 50, TIME);
+    let deposit = Deposit::new(pos(0, 0), 3, 3, ResourceType::Type0);
+    place_building(&mut sim, Building::Deposit(deposit)).unwrap();
+    let deposit = Deposit::new(pos(27, 0), 3, 3, ResourceType::Type1);
+    place_building(&mut sim, Building::Deposit(deposit)).unwrap();
+
+    let config = SolverConfig {
+        budget: Budget::Iterations(40),
+        ..SolverConfig::default()
+    };
+    let cancel = CancelToken::new();
+    let solution = crate::solve_sim(
+        &sim,
+        config,
+        Instant::now(),
+        &cancel,
+        |_| (),
+        |best| best.cloned(),
+    )
+    .unwrap();
+
+    // a single factory can only make one of the products out of 45 resources
+    assert_eq!(solution.run.points, 300);
+}
+
+#[test]
+fn plan_shared_deposit_resources() {
+    let mut products = Products::default();
+    products[ProductType::Type0] = Product::new(Resources::new([3, 0, 0, 0, 0, 0, 0, 0]), 10);
+    products[ProductType::Type1] = Product::new(Resources::new([2, 2, 0, 0, 0, 0, 0, 0]), 15);
+    let mut sim = Sim::new(products, Board::new(20, 20), TURNS, TIME);
+    let deposit = Deposit::new(pos(0, 0), 3, 3, ResourceType::Type0);
+    let d0 = place_building(&mut sim, Building::Deposit(deposit)).unwrap();
+    let deposit = Deposit::new(pos(18, 18), 2, 2, ResourceType::Type1);
+    let d1 = place_building(&mut sim, Building::Deposit(deposit)).unwrap();
+
+    let regions = find_regions(&sim);
+    assert_eq!(regions.len(), 1);
+    let plan = plan_resources(&sim, regions.get(0).deposits);
+
+    // all 20 resources of type 1 go into product 1, the rest of deposit 0 into product 0
+    let product1 = plan.get(ProductType::Type1).unwrap();
+    assert_eq!(product1.count, 10);
+    assert_eq!(product1.deposits, vec![(d0, 20), (d1, 20)]);
+    let product0 = plan.get(ProductType::Type0).unwrap();
+    assert_eq!(product0.count, 8);
+    assert_eq!(product0.deposits, vec![(d0, 24)]);
+    let points: u32 = plan.products.iter().map(|p| p.points).sum();
+    assert_eq!(points, 230);
+}
+
+#[test]
+fn improvement_phase_never_makes_the_solution_worse() {
+    let input = std::fs::read_to_string("../tasks/002/task.json").unwrap();
+    let task: dto::Task = serde_json::from_str(&input).unwrap();
+    let score = |config: SolverConfig| {
+        let solution = solve_task("../tasks/002/task.json", config);
+        let mut sim = Sim::try_from(&task).unwrap();
+        solution.place(&mut sim).unwrap();
+        sim::run(&sim)
+    };
+
+    let config = SolverConfig {
+        seed: 3,
+        budget: Budget::Iterations(6),
+        improvement_steps: 0,
+        ..SolverConfig::default()
+    };
+    let searched = score(config.clone());
+    let improved = score(SolverConfig {
+        improvement_steps: 300,
+        ..config
+    });
+
+    assert!(improved >= searched, "{improved} < {searched}");
+}